@@ -1,5 +1,10 @@
 use clap::{Parser, Subcommand, ValueEnum};
-use log::LevelFilter;
+use libretro::machine::apple_ii_roms::AppleModel;
+use libretro::machine::apple_iie_e_display::Renderer;
+use libretro::machine::monitor::Phosphor;
+use libretro::machine::viewport::{Scaling, Viewport};
+use libretro::memory::RamInit;
+use log::{LevelFilter, warn};
 use std::fmt::Debug;
 use std::fs::File;
 use std::path::{Path, PathBuf};
@@ -14,8 +19,32 @@ struct Cli {
     #[arg(long, value_name = "LOGLEVEL", default_value = "info")]
     loglevel: MyLogLevel,
 
+    /// Where frames are shown
+    #[arg(long, value_name = "DISPLAY", default_value = "window")]
+    display: DisplayKind,
+
+    /// Run from power on and save a screenshot once this many cycles have passed
+    #[arg(long, value_name = "CYCLE")]
+    screenshot_at_cycle: Option<usize>,
+
+    /// File written by --screenshot-at-cycle
+    #[arg(long, value_name = "FILE", default_value = "screenshot.png")]
+    screenshot: PathBuf,
+
+    /// Window size as a multiple of the frame, saved for next time
+    #[arg(long, value_name = "SCALE")]
+    scale: Option<u32>,
+
+    /// Window scaling: integer or fit, saved for next time
+    #[arg(long, value_name = "SCALING")]
+    scaling: Option<Scaling>,
+
+    /// Stretch the frame to 4:3, saved for next time
+    #[arg(long, value_name = "BOOL")]
+    aspect: Option<bool>,
+
     #[command(subcommand)]
-    machine: Machines,
+    command: Command,
 }
 
 #[derive(ValueEnum, Debug, Clone, Default)]
@@ -29,6 +58,26 @@ enum MyLogLevel {
     Trace,
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum DisplayKind {
+    /// A window drawn with the GPU
+    #[default]
+    Window,
+    /// No output, frames are only used for screenshots and recordings
+    Headless,
+    /// The text screen drawn in the terminal with ANSI escapes
+    Tty,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[command(flatten)]
+    Machine(Machines),
+
+    /// Identify a ROM image against the known-ROM database
+    RomInfo { file: PathBuf },
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum Machines {
     AppleIiE {
@@ -40,13 +89,54 @@ pub enum Machines {
 
         #[arg(long, value_name = "DISK2")]
         disk2: Option<PathBuf>,
+
+        /// Emulate an Enhanced //e (65C02 ROMs and MouseText)
+        #[arg(long)]
+        enhanced: bool,
+
+        /// RAM contents at power on: zero, apple2e, fixed:<byte> or random:<seed>
+        #[arg(long, value_name = "PATTERN", default_value = "apple2e")]
+        ram_init: RamInit,
+
+        /// Show hi-res graphics as on a monochrome monitor
+        #[arg(long)]
+        monochrome: bool,
+
+        /// Video output: rgb, ntsc or ntsc:<luma width>,<chroma width>
+        #[arg(long, value_name = "RENDERER", default_value = "rgb")]
+        renderer: Renderer,
+
+        /// Monitor phosphor: color, green, amber or white
+        #[arg(long, value_name = "PHOSPHOR", default_value = "color")]
+        phosphor: Phosphor,
+
+        /// Darken the gaps between scanlines
+        #[arg(long)]
+        scanlines: bool,
+
+        /// File of 16 RRGGBB colours to use instead of the built-in rgb renderer palette
+        #[arg(long, value_name = "FILE")]
+        palette: Option<PathBuf>,
     },
 }
 
+impl Machines {
+    pub fn model(&self) -> AppleModel {
+        match self {
+            Machines::AppleIiE { enhanced: true, .. } => AppleModel::AppleIIeEnhanced,
+            Machines::AppleIiE { .. } => AppleModel::AppleIIe,
+        }
+    }
+}
+
 // #[derive(Debug)]
 pub struct Config {
     prefix: PathBuf,
-    pub machine: Machines,
+    pub command: Command,
+    pub display: DisplayKind,
+    pub screenshot_at_cycle: Option<usize>,
+    pub screenshot: PathBuf,
+    pub viewport: Viewport,
 }
 
 impl Config {
@@ -55,9 +145,13 @@ impl Config {
         let prefix = shellexpand::tilde(retro_path.as_str()).to_string();
 
         let cli = Cli::parse();
-        let config = Self {
+        let mut config = Self {
             prefix: PathBuf::from(prefix),
-            machine: cli.machine,
+            command: cli.command,
+            display: cli.display,
+            screenshot_at_cycle: cli.screenshot_at_cycle,
+            screenshot: cli.screenshot,
+            viewport: Viewport::default(),
         };
 
         let loglevel = match cli.loglevel {
@@ -81,14 +175,43 @@ impl Config {
             .target(env_logger::Target::Pipe(logfile))
             .init();
 
+        config.viewport = config.load_viewport();
+        if let Some(scale) = cli.scale {
+            config.viewport.scale = scale.max(1);
+        }
+        if let Some(scaling) = cli.scaling {
+            config.viewport.scaling = scaling;
+        }
+        if let Some(aspect) = cli.aspect {
+            config.viewport.aspect = aspect;
+        }
         config
     }
 
+    /// Where the window scale settings are kept
+    pub fn viewport_path(&self) -> PathBuf {
+        self.get_full_path("window.cfg")
+    }
+
+    fn load_viewport(&self) -> Viewport {
+        let Ok(settings) = self.get_file_string("window.cfg") else {
+            return Viewport::default();
+        };
+        settings.parse().unwrap_or_else(|err| {
+            warn!("Ignoring {}: {}", self.viewport_path().display(), err);
+            Viewport::default()
+        })
+    }
+
     fn get_full_path<T: AsRef<Path>>(&self, path: T) -> PathBuf {
         self.prefix.join(path)
     }
 
-    #[allow(dead_code)]
+    /// Directories searched for ROM images, in order
+    pub fn rom_paths(&self) -> Vec<PathBuf> {
+        vec![self.get_full_path("rom"), self.prefix.clone()]
+    }
+
     pub fn get_file_string<T: AsRef<Path>>(&self, p: T) -> io::Result<String> {
         let path = self.get_full_path(p);
         fs::read_to_string(path)
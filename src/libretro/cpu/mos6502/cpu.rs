@@ -199,7 +199,8 @@ impl MOS6502 {
         trace!("execute()");
 
         let pc = self.pc;
-        let opcode = self.fetch(mem);
+        let opcode = mem.read_opcode(pc as usize);
+        self.pc = self.pc.wrapping_add(1);
         let mut bytes = vec![opcode];
 
         #[bitmatch]
@@ -4,4 +4,5 @@ mod instruction;
 mod cpu_tests;
 
 pub use cpu::MOS6502;
-pub use addressing_mode::AddressingMode;
\ No newline at end of file
+pub use addressing_mode::AddressingMode;
+pub use instruction::Instruction;
\ No newline at end of file
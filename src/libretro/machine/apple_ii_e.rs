@@ -1,16 +1,42 @@
-use crate::cpu::mos6502::MOS6502;
-use crate::machine::Machine;
-use crate::memory::{Endian, Memory, MemoryManager, RAM, ROM, VRAM};
-use crate::DisplayCommand;
+use crate::cpu::mos6502::{Instruction, MOS6502};
+use crate::machine::apple_ii_e_mmu::{BankedRAM, Keyboard, SoftSwitches, Switches};
+use crate::machine::apple_ii_roms::{self, AppleModel};
+use crate::machine::apple_iie_e_display::{
+    DISPLAY_HEIGHT, DISPLAY_WIDTH, Display, FLASH_FRAMES, Renderer, TextScreen,
+};
+use crate::machine::monitor::{Monitor, Palette};
+use crate::machine::video_scanner::{
+    self, CYCLES_PER_FRAME, VISIBLE_COLUMNS, VISIBLE_LINES, VideoScanner,
+};
+use crate::machine::{Machine, WatchReport};
+use crate::memory::{Endian, Memory, MemoryID, MemoryManager, ROM, RamInit, RomError};
 use log::{debug, trace};
+use std::cell::Cell;
 use std::fs::File;
-use std::sync::mpsc;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
 
 pub struct AppleIIe {
     cpu: MOS6502,
     memory: MemoryManager,
+    main_ram: MemoryID,
+    switches: Rc<Cell<Switches>>,
+    keyboard: Rc<Cell<Keyboard>>,
+    display: Display,
+    monitor: Monitor,
+    /// Frame drawn by the beam so far
+    frame: Vec<u8>,
+    /// Next cycle for the beam to draw
+    beam: usize,
+    /// Switches as the beam passed each column of the current line
+    line_switches: [Switches; VISIBLE_COLUMNS],
+    frame_done: bool,
+    /// Cycles since power on
+    clock: Rc<Cell<usize>>,
     disk1: Option<File>,
     disk2: Option<File>,
+    watch_hits: Vec<WatchReport>,
 }
 
 impl Machine for AppleIIe {
@@ -30,13 +56,22 @@ impl Machine for AppleIIe {
 
     fn cycle(&mut self) {
         trace!("cycle()");
-        if let Some(i) = self.cpu.cycle(&mut self.memory) {
+        let done = self.cpu.cycle(&mut self.memory);
+        self.clock.set(self.clock.get() + 1);
+        self.scan();
+        self.collect_watch_hits(done.as_ref());
+        if let Some(i) = done {
             debug!("{}", i);
         }
     }
 
     fn step(&mut self) {
-        debug!("{}", self.cpu.step(&mut self.memory));
+        let start = self.cpu.cycles;
+        let i = self.cpu.step(&mut self.memory);
+        self.clock.set(self.clock.get() + self.cpu.cycles - start);
+        self.scan();
+        self.collect_watch_hits(Some(&i));
+        debug!("{}", i);
         debug!("{:?} {}", self.cpu, self.get_stack());
     }
 
@@ -47,48 +82,241 @@ impl Machine for AppleIIe {
     fn write(&mut self, addr: usize, data: u8) {
         self.memory.write(addr, data);
     }
+
+    fn get_memory(&mut self) -> &mut MemoryManager {
+        &mut self.memory
+    }
+
+    fn take_watch_hits(&mut self) -> Vec<WatchReport> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    fn clock(&self) -> usize {
+        self.clock.get()
+    }
+
+    fn take_frame_done(&mut self) -> bool {
+        std::mem::take(&mut self.frame_done)
+    }
+
+    fn redraw(&mut self) {
+        let flash = self.flash();
+        if let Some(ram) = self.memory.region(self.main_ram) {
+            let (main, aux) = ram.get_raw().split_at(0x10000);
+            self.display
+                .render(&mut self.frame, main, aux, self.switches.get(), flash);
+        }
+    }
+
+    fn render(&self, frame: &mut [u8]) {
+        frame.copy_from_slice(&self.frame);
+        self.monitor.apply(frame, self.switches.get());
+    }
+
+    fn text_screen(&self) -> TextScreen {
+        let ram = self
+            .memory
+            .region(self.main_ram)
+            .expect("Main RAM is mapped");
+        let (main, aux) = ram.get_raw().split_at(0x10000);
+        let mousetext = self.display.has_mousetext();
+        TextScreen::read(main, aux, self.switches.get(), self.flash(), mousetext)
+    }
+
+    fn key_down(&mut self, code: u8) {
+        let mut keyboard = self.keyboard.get();
+        keyboard.press(code);
+        self.keyboard.set(keyboard);
+    }
+
+    fn key_up(&mut self) {
+        let mut keyboard = self.keyboard.get();
+        keyboard.down = false;
+        self.keyboard.set(keyboard);
+    }
+
+    fn set_apple_keys(&mut self, open: bool, solid: bool) {
+        let mut keyboard = self.keyboard.get();
+        keyboard.open_apple = open;
+        keyboard.solid_apple = solid;
+        self.keyboard.set(keyboard);
+    }
 }
 
 impl AppleIIe {
-    pub fn new(gui_tx: mpsc::Sender<DisplayCommand>) -> Self {
+    pub fn new(
+        rom_paths: &[PathBuf],
+        model: AppleModel,
+        ram_init: RamInit,
+        renderer: Renderer,
+    ) -> Result<Self, RomError> {
         trace!("new()");
-        let mut mm = MemoryManager::new(0xFFFF);
-        mm.map(0, Box::new(RAM::new(0x10000, Endian::Little)));
+        let rom = |size, name| -> Result<ROM, RomError> {
+            let rom = ROM::new(size, Endian::Little, name, rom_paths)?;
+            apple_ii_roms::check(name, rom.get_raw(), model);
+            Ok(rom)
+        };
 
-        // Text page 0
-        mm.map(0x0400, Box::new(VRAM::new(0x400, Endian::Little, gui_tx)));
+        let display = Display::new(rom_paths, model, renderer)?;
+        let clock = Rc::new(Cell::new(0));
+        let switches = Rc::new(Cell::new(Switches::default()));
+        let keyboard = Rc::new(Cell::new(Keyboard::default()));
 
-        // Monitor ROM
-        mm.map(
-            0xF800,
-            // Box::new(ROM::new(0x800, Endian::Little, "apple2e_F8.bin")),
-            Box::new(ROM::new(0x800, Endian::Little, "apple2e_vtest.bin")),
+        let mut mm = MemoryManager::new(0xFFFF);
+        mm.set_floating_bus(Box::new(VideoScanner::new(clock.clone(), switches.clone())));
+        let main_ram = mm.map(
+            0,
+            "Main RAM",
+            Box::new(BankedRAM::new(ram_init, switches.clone())),
         );
-
-        // 80-Column Card
         mm.map(
-            0xC100,
-            Box::new(ROM::new(0x300, Endian::Little, "apple2e_C1.bin")),
+            0xC000,
+            "MMU Soft Switches",
+            Box::new(SoftSwitches::new(
+                0xC000,
+                0x20,
+                switches.clone(),
+                clock.clone(),
+                keyboard.clone(),
+            )),
         );
         mm.map(
-            0xC800,
-            Box::new(ROM::new(0x800, Endian::Little, "apple2e_C8.bin")),
+            0xC050,
+            "Display Soft Switches",
+            Box::new(SoftSwitches::new(
+                0xC050,
+                0x10,
+                switches.clone(),
+                clock.clone(),
+                keyboard.clone(),
+            )),
         );
-
-        // Integer BASIC
         mm.map(
-            0xE000,
-            Box::new(ROM::new(0x1800, Endian::Little, "apple2e_ibasic_E0.bin")),
+            0xC060,
+            "Game I/O",
+            Box::new(SoftSwitches::new(
+                0xC060,
+                0x10,
+                switches.clone(),
+                clock.clone(),
+                keyboard.clone(),
+            )),
         );
 
+        // Whole CD and EF images are identified as dumped and sliced into place,
+        // otherwise the ROMs are loaded as separate pieces
+        let optional = |result: Result<ROM, RomError>| match result {
+            Err(RomError::NotFound { .. }) => Ok(None),
+            result => result.map(Some),
+        };
+        let cd = optional(rom(0x2000, "apple2e_cd.bin"))?;
+        let ef = optional(rom(0x2000, "apple2e_ef.bin"))?;
+        if let (Some(cd), Some(ef)) = (cd, ef) {
+            let slice = |range: Range<usize>| {
+                Box::new(ROM::from_bytes(
+                    Endian::Little,
+                    cd.get_raw()[range].to_vec(),
+                ))
+            };
+            mm.map(0xC100, "80-Column Firmware", slice(0x100..0x400));
+            mm.map(0xC800, "80-Column Expansion ROM", slice(0x800..0x1000));
+            mm.map(0xD000, "Applesoft ROM", slice(0x1000..0x2000));
+            mm.map(0xE000, "Applesoft and Monitor ROM", Box::new(ef));
+        } else {
+            // Monitor ROM
+            mm.map(
+                0xF800,
+                "Monitor ROM",
+                // Box::new(rom(0x800, "apple2e_F8.bin")?),
+                Box::new(rom(0x800, "apple2e_vtest.bin")?),
+            );
+
+            // 80-Column Card
+            mm.map(
+                0xC100,
+                "80-Column Firmware",
+                Box::new(rom(0x300, "apple2e_C1.bin")?),
+            );
+            mm.map(
+                0xC800,
+                "80-Column Expansion ROM",
+                Box::new(rom(0x800, "apple2e_C8.bin")?),
+            );
+
+            // Integer BASIC
+            mm.map(
+                0xE000,
+                "Integer BASIC ROM",
+                Box::new(rom(0x1800, "apple2e_ibasic_E0.bin")?),
+            );
+        }
+
         let mut mach = Self {
             cpu: MOS6502::new(),
             memory: mm,
+            main_ram,
+            switches,
+            keyboard,
+            display,
+            monitor: Monitor::default(),
+            frame: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4],
+            beam: 0,
+            line_switches: [Switches::default(); VISIBLE_COLUMNS],
+            frame_done: false,
+            clock,
             disk1: None,
             disk2: None,
+            watch_hits: Vec::new(),
         };
         mach.reset();
-        mach
+        Ok(mach)
+    }
+
+    pub fn set_monochrome(&mut self, monochrome: bool) {
+        self.display.set_monochrome(monochrome);
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.display.set_palette(palette);
+    }
+
+    pub fn set_monitor(&mut self, monitor: Monitor) {
+        self.monitor = monitor;
+    }
+
+    fn flash(&self) -> bool {
+        (self.clock.get() / CYCLES_PER_FRAME / FLASH_FRAMES) % 2 == 1
+    }
+
+    /// Moves the beam up to the clock, drawing each visible line once the beam has passed it
+    ///
+    /// Switches changed by an instruction take effect from the cycle after it ends.
+    fn scan(&mut self) {
+        let switches = self.switches.get();
+        while self.beam < self.clock.get() {
+            if let Some((line, col)) = video_scanner::beam_position(self.beam) {
+                self.line_switches[col] = switches;
+                if col == VISIBLE_COLUMNS - 1 {
+                    self.draw_line(line);
+                    self.frame_done |= line == VISIBLE_LINES - 1;
+                }
+            }
+            self.beam += 1;
+        }
+    }
+
+    fn draw_line(&mut self, line: usize) {
+        let flash = self.flash();
+        if let Some(ram) = self.memory.region(self.main_ram) {
+            let (main, aux) = ram.get_raw().split_at(0x10000);
+            self.display.render_line(
+                &mut self.frame,
+                line,
+                (main, aux),
+                &self.line_switches,
+                flash,
+            );
+        }
     }
 
     pub fn load_disk1(&mut self, disk: File) {
@@ -107,8 +335,22 @@ impl AppleIIe {
         self.disk2 = None;
     }
 
-    pub fn get_memory(&mut self) -> &mut MemoryManager {
-        &mut self.memory
+    /// Pairs new watchpoint hits with the instruction that caused them
+    fn collect_watch_hits(&mut self, done: Option<&Instruction>) {
+        let hits = self.memory.take_watch_hits();
+        if hits.is_empty() {
+            return;
+        }
+        let instruction = done
+            .or(self.cpu.current.as_ref())
+            .map(|i| i.to_string())
+            .unwrap_or_default();
+        for hit in hits {
+            self.watch_hits.push(WatchReport {
+                hit,
+                instruction: instruction.clone(),
+            });
+        }
     }
 
     fn get_stack(&self) -> String {
@@ -120,3 +362,134 @@ impl AppleIIe {
         s
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::apple_ii_roms::AppleModel;
+    use crate::machine::apple_iie_e_display::{DISPLAY_HEIGHT, DISPLAY_WIDTH, Renderer};
+    use crate::machine::video_scanner::{
+        CYCLES_PER_FRAME, VISIBLE_COLUMNS, beam_position, scanner_address,
+    };
+    use crate::machine::{AppleIIe, Machine};
+    use crate::memory::{Memory, RamInit};
+
+    /// A //e with blank ROMs, which are padded with $FF
+    fn machine(test: &str) -> AppleIIe {
+        let dir = std::env::temp_dir().join(format!("apple2e-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["apple2e_video.bin", "apple2e_cd.bin", "apple2e_ef.bin"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+        AppleIIe::new(&[dir], AppleModel::AppleIIe, RamInit::Zero, Renderer::Rgb).unwrap()
+    }
+
+    #[test]
+    fn apple_ii_e_floating_bus() {
+        let mut mach = machine("floating-bus");
+        for addr in 0x400..0x800 {
+            mach.write(addr, 0x80 | addr as u8);
+        }
+        mach.clock.set(12345);
+        let scanned = || {
+            mach.memory
+                .peek(scanner_address(12345, mach.switches.get().into()))
+        };
+        let expected = scanned();
+        assert_ne!(expected, 0);
+
+        // Empty I/O space and switches that only change state read the scanner's byte
+        assert_eq!(mach.read(0xC020), expected);
+        assert_eq!(mach.read(0xC050), expected);
+        assert!(!mach.switches.get().text);
+        assert_eq!(mach.read(0xC064), scanned());
+        assert_eq!(mach.read(0xC0F0), scanned());
+
+        // Status reads still drive the bus
+        assert_eq!(mach.read(0xC01A), 0x00);
+    }
+
+    #[test]
+    fn apple_ii_e_mid_line_switch() {
+        let mut mach = machine("mid-line-switch");
+        let frame = |mach: &AppleIIe| {
+            let mut frame = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+            mach.render(&mut frame);
+            frame
+        };
+
+        // Whole frames of rubout text and of white lo-res to compare against
+        for addr in 0x400..0x800 {
+            mach.write(addr, 0xFF);
+        }
+        mach.redraw();
+        let text = frame(&mach);
+        mach.write(0xC050, 0);
+        mach.redraw();
+        let lores = frame(&mach);
+        assert_ne!(text, lores);
+
+        // Turn TEXT off as the beam reaches column 20 of line 8
+        mach.write(0xC051, 0);
+        let at = (0..CYCLES_PER_FRAME)
+            .find(|&cycle| beam_position(cycle) == Some((8, 20)))
+            .unwrap();
+        mach.clock.set(at);
+        mach.scan();
+        mach.write(0xC050, 0);
+        mach.clock.set(CYCLES_PER_FRAME);
+        mach.scan();
+        assert!(mach.take_frame_done());
+        let mixed = frame(&mach);
+
+        let row =
+            |frame: &[u8], y: usize| frame[y * DISPLAY_WIDTH * 4..][..DISPLAY_WIDTH * 4].to_vec();
+        let split = 20 * DISPLAY_WIDTH / VISIBLE_COLUMNS * 4;
+        assert_ne!(row(&text, 8 * 2)[..split], row(&lores, 8 * 2)[..split]);
+        assert_ne!(row(&text, 8 * 2)[split..], row(&lores, 8 * 2)[split..]);
+        assert_eq!(row(&mixed, 7 * 2), row(&text, 7 * 2));
+        assert_eq!(row(&mixed, 8 * 2)[..split], row(&text, 8 * 2)[..split]);
+        assert_eq!(row(&mixed, 8 * 2)[split..], row(&lores, 8 * 2)[split..]);
+        assert_eq!(row(&mixed, 9 * 2), row(&lores, 9 * 2));
+    }
+
+    #[test]
+    fn apple_ii_e_screen_text() {
+        let mut mach = machine("screen-text");
+        for (i, code) in [0xC1, 0x01, 0xFF, 0x7F].into_iter().enumerate() {
+            mach.write(0x400 + i, code);
+        }
+
+        // Normal and inverse 'A', rubout, then a flashing '?' in the primary set
+        let rows = mach.screen_text();
+        assert_eq!(rows.len(), 24);
+        assert_eq!(rows[0].chars().count(), 40);
+        assert!(rows[0].starts_with("AA░?@"), "{}", rows[0]);
+
+        // The alternate set has an inverse rubout at $7F instead
+        mach.write(0xC00F, 0);
+        assert!(mach.screen_text()[0].starts_with("AA░░@"));
+    }
+
+    #[test]
+    fn apple_ii_e_video_memory() {
+        let mut mach = machine("video-memory");
+        let mut frame = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+
+        // The screen is read from main RAM, so writes through the bus show up
+        mach.write(0x400, 0xC1);
+        assert_eq!(mach.read(0x400), 0xC1);
+        assert_eq!(mach.text_screen().rows[0][0], 0xC1);
+
+        mach.write(0xC050, 0);
+        mach.write(0xC057, 0);
+        mach.redraw();
+        mach.render(&mut frame);
+        assert_eq!(frame[0..4], [0, 0, 0, 0xFF]);
+
+        mach.write(0x2000, 0x7F);
+        assert_eq!(mach.read(0x2000), 0x7F);
+        mach.redraw();
+        mach.render(&mut frame);
+        assert_ne!(frame[0..3], [0, 0, 0]);
+    }
+}
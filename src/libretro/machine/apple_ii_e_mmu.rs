@@ -0,0 +1,496 @@
+/**
+ * Apple //e memory management unit
+ *
+ * The MMU switches reads and writes between the main 64K and the 64K of
+ * auxiliary memory on the Extended 80-Column Card. Its soft switches live at
+ * $C000-$C01F, with the display switches at $C050-$C05F. $C019 reports
+ * vertical blanking from the video scanner. The keyboard shares the same ports:
+ * $C000-$C00F read the last key pressed and $C010 clears its strobe, while the
+ * Open Apple and Solid Apple keys read as buttons at $C061 and $C062.
+ */
+use crate::machine::video_scanner;
+use crate::memory::{Memory, RamInit};
+use std::cell::Cell;
+use std::rc::Rc;
+
+const BANK_SIZE: usize = 0x10000;
+/// I/O and slot ROM space, which RAM leaves to the other regions
+const IO_SPACE: std::ops::Range<usize> = 0xC000..0xD000;
+
+/// Soft switch state shared between the MMU, its I/O ports and the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Switches {
+    /// PAGE2 selects auxiliary display memory instead of display page 2
+    pub store80: bool,
+    pub ramrd: bool,
+    pub ramwrt: bool,
+    pub altzp: bool,
+    pub col80: bool,
+    pub altcharset: bool,
+    pub text: bool,
+    pub mixed: bool,
+    pub page2: bool,
+    pub hires: bool,
+    /// Double hi-res, enabled by turning annunciator 3 off
+    pub dhires: bool,
+}
+
+impl Default for Switches {
+    fn default() -> Self {
+        Self {
+            store80: false,
+            ramrd: false,
+            ramwrt: false,
+            altzp: false,
+            col80: false,
+            altcharset: false,
+            text: true,
+            mixed: false,
+            page2: false,
+            hires: false,
+            dhires: false,
+        }
+    }
+}
+
+impl Switches {
+    /// Whether an access to `addr` goes to auxiliary memory
+    fn is_aux(&self, addr: usize, write: bool) -> bool {
+        match addr {
+            0x0000..=0x01FF => self.altzp,
+            0x0400..=0x07FF if self.store80 => self.page2,
+            0x2000..=0x3FFF if self.store80 && self.hires => self.page2,
+            0x0200..=0xBFFF if write => self.ramwrt,
+            0x0200..=0xBFFF => self.ramrd,
+            _ => false,
+        }
+    }
+}
+
+/// Keyboard state shared between the keyboard ports and the host
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Keyboard {
+    /// Code of the last key pressed, with bit 7 set until the strobe is cleared
+    pub latch: u8,
+    /// Whether any key is held down
+    pub down: bool,
+    pub open_apple: bool,
+    pub solid_apple: bool,
+}
+
+impl Keyboard {
+    /// Latches a key with a 7-bit ASCII code
+    pub fn press(&mut self, code: u8) {
+        self.latch = code | 0x80;
+        self.down = true;
+    }
+}
+
+/// Main and auxiliary RAM, banked by the MMU soft switches
+///
+/// `get_raw` returns main memory followed by auxiliary memory. $C000-$CFFF is
+/// not mapped, so reads there reach the soft switches or the floating bus.
+pub struct BankedRAM {
+    memory: Vec<u8>,
+    switches: Rc<Cell<Switches>>,
+}
+
+impl BankedRAM {
+    pub fn new(init: RamInit, switches: Rc<Cell<Switches>>) -> Self {
+        let mut memory = vec![0; BANK_SIZE * 2];
+        init.fill(&mut memory);
+        Self { memory, switches }
+    }
+
+    fn index(&self, addr: usize, write: bool) -> usize {
+        if self.switches.get().is_aux(addr, write) {
+            BANK_SIZE + addr
+        } else {
+            addr
+        }
+    }
+}
+
+impl Memory for BankedRAM {
+    fn write(&mut self, addr: usize, data: u8) {
+        if self.is_valid(addr) {
+            let index = self.index(addr, true);
+            self.memory[index] = data;
+        } else {
+            panic!("Address out of bounds: {:?}", addr);
+        }
+    }
+
+    fn read(&self, addr: usize) -> u8 {
+        if self.is_valid(addr) {
+            self.memory[self.index(addr, false)]
+        } else {
+            panic!("Address out of bounds: {:?}", addr);
+        }
+    }
+
+    fn is_valid(&self, addr: usize) -> bool {
+        addr < BANK_SIZE && !IO_SPACE.contains(&addr)
+    }
+
+    fn read_word(&self, addr: usize) -> u16 {
+        let low = self.read(addr) as u16;
+        let high = self.read(addr + 1) as u16;
+        high << 8 | low
+    }
+
+    fn write_word(&mut self, addr: usize, word: u16) {
+        self.write(addr, word as u8);
+        self.write(addr + 1, (word >> 8) as u8);
+    }
+
+    fn read_word_zero(&self, addr: u8) -> u16 {
+        let low = self.read(addr as usize) as u16;
+        let high = self.read(addr.wrapping_add(1) as usize) as u16;
+        high << 8 | low
+    }
+
+    fn write_word_zero(&mut self, addr: u8, word: u16) {
+        self.write(addr as usize, word as u8);
+        self.write(addr.wrapping_add(1) as usize, (word >> 8) as u8);
+    }
+
+    fn size(&self) -> usize {
+        BANK_SIZE
+    }
+
+    fn get_raw(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+}
+
+/// Soft switch I/O ports mapped at `base`
+pub struct SoftSwitches {
+    base: usize,
+    size: usize,
+    switches: Rc<Cell<Switches>>,
+    /// Cycles since power on, for the vertical blank status
+    clock: Rc<Cell<usize>>,
+    keyboard: Rc<Cell<Keyboard>>,
+}
+
+impl SoftSwitches {
+    pub fn new(
+        base: usize,
+        size: usize,
+        switches: Rc<Cell<Switches>>,
+        clock: Rc<Cell<usize>>,
+        keyboard: Rc<Cell<Keyboard>>,
+    ) -> Self {
+        Self {
+            base,
+            size,
+            switches,
+            clock,
+            keyboard,
+        }
+    }
+
+    /// Switches that are set by either reading or writing
+    fn touch(&self, addr: usize) {
+        let mut s = self.switches.get();
+        match addr {
+            0xC010 => {
+                let mut k = self.keyboard.get();
+                k.latch &= 0x7F;
+                return self.keyboard.set(k);
+            }
+            0xC050 => s.text = false,
+            0xC051 => s.text = true,
+            0xC052 => s.mixed = false,
+            0xC053 => s.mixed = true,
+            0xC054 => s.page2 = false,
+            0xC055 => s.page2 = true,
+            0xC056 => s.hires = false,
+            0xC057 => s.hires = true,
+            0xC05E => s.dhires = true,
+            0xC05F => s.dhires = false,
+            _ => return,
+        }
+        self.switches.set(s);
+    }
+}
+
+fn status(on: bool) -> u8 {
+    if on { 0x80 } else { 0x00 }
+}
+
+impl Memory for SoftSwitches {
+    fn write(&mut self, addr: usize, _data: u8) {
+        let addr = self.base + addr;
+        let mut s = self.switches.get();
+        match addr {
+            0xC000 => s.store80 = false,
+            0xC001 => s.store80 = true,
+            0xC002 => s.ramrd = false,
+            0xC003 => s.ramrd = true,
+            0xC004 => s.ramwrt = false,
+            0xC005 => s.ramwrt = true,
+            0xC008 => s.altzp = false,
+            0xC009 => s.altzp = true,
+            0xC00C => s.col80 = false,
+            0xC00D => s.col80 = true,
+            0xC00E => s.altcharset = false,
+            0xC00F => s.altcharset = true,
+            _ => return self.touch(addr),
+        }
+        self.switches.set(s);
+    }
+
+    fn read(&self, addr: usize) -> u8 {
+        let data = self.peek(addr);
+        self.touch(self.base + addr);
+        data
+    }
+
+    fn peek(&self, addr: usize) -> u8 {
+        let addr = self.base + addr;
+        let s = self.switches.get();
+        let k = self.keyboard.get();
+        // The low bits of the status ports come from the keyboard
+        let key = k.latch & 0x7F;
+        match addr {
+            0xC000..=0xC00F => k.latch,
+            0xC010 => status(k.down) | key,
+            0xC013 => status(s.ramrd) | key,
+            0xC014 => status(s.ramwrt) | key,
+            0xC016 => status(s.altzp) | key,
+            0xC018 => status(s.store80) | key,
+            // Low during vertical blanking
+            0xC019 => status(!video_scanner::is_vblank(self.clock.get())) | key,
+            0xC01A => status(s.text) | key,
+            0xC01B => status(s.mixed) | key,
+            0xC01C => status(s.page2) | key,
+            0xC01D => status(s.hires) | key,
+            0xC01E => status(s.altcharset) | key,
+            0xC01F => status(s.col80) | key,
+            // Switches that are not emulated read as off
+            0xC011..=0xC01F => key,
+            0xC061 => status(k.open_apple),
+            0xC062 => status(k.solid_apple),
+            _ => 0,
+        }
+    }
+
+    fn is_valid(&self, addr: usize) -> bool {
+        addr < self.size
+    }
+
+    /// Ports that only switch something return whatever the video scanner is fetching
+    fn is_floating(&self, addr: usize) -> bool {
+        !matches!(self.base + addr, 0xC000..=0xC01F | 0xC061 | 0xC062)
+    }
+
+    fn read_word(&self, addr: usize) -> u16 {
+        let low = self.read(addr) as u16;
+        let high = self.read(addr + 1) as u16;
+        high << 8 | low
+    }
+
+    fn write_word(&mut self, addr: usize, word: u16) {
+        self.write(addr, word as u8);
+        self.write(addr + 1, (word >> 8) as u8);
+    }
+
+    fn read_word_zero(&self, addr: u8) -> u16 {
+        self.read_word(addr as usize)
+    }
+
+    fn write_word_zero(&mut self, addr: u8, word: u16) {
+        self.write_word(addr as usize, word);
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn get_raw(&self) -> &[u8] {
+        &[]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::apple_ii_e_mmu::{BankedRAM, Keyboard, SoftSwitches, Switches};
+    use crate::machine::video_scanner::CYCLES_PER_LINE;
+    use crate::memory::{Memory, MemoryManager, RamInit, tools};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    type Machine = (
+        MemoryManager,
+        Rc<Cell<Switches>>,
+        Rc<Cell<usize>>,
+        Rc<Cell<Keyboard>>,
+    );
+
+    fn machine() -> Machine {
+        let switches = Rc::new(Cell::new(Switches::default()));
+        let clock = Rc::new(Cell::new(0));
+        let keyboard = Rc::new(Cell::new(Keyboard::default()));
+        let mut mm = MemoryManager::new(0xFFFF);
+        mm.map(
+            0,
+            "RAM",
+            Box::new(BankedRAM::new(RamInit::Zero, switches.clone())),
+        );
+        mm.map(
+            0xC000,
+            "MMU",
+            Box::new(SoftSwitches::new(
+                0xC000,
+                0x20,
+                switches.clone(),
+                clock.clone(),
+                keyboard.clone(),
+            )),
+        );
+        mm.map(
+            0xC050,
+            "Display",
+            Box::new(SoftSwitches::new(
+                0xC050,
+                0x10,
+                switches.clone(),
+                clock.clone(),
+                keyboard.clone(),
+            )),
+        );
+        mm.map(
+            0xC060,
+            "Game I/O",
+            Box::new(SoftSwitches::new(
+                0xC060,
+                0x10,
+                switches.clone(),
+                clock.clone(),
+                keyboard.clone(),
+            )),
+        );
+        (mm, switches, clock, keyboard)
+    }
+
+    #[test]
+    fn mmu_ramrd_ramwrt() {
+        let (mut mm, switches, _, _) = machine();
+        mm.write(0x2000, 0x11);
+        mm.write(0xC005, 0);
+        mm.write(0x2000, 0x22);
+        assert_eq!(mm.read(0x2000), 0x11);
+        assert_eq!(mm.read(0xC014), 0x80);
+
+        mm.write(0xC003, 0);
+        assert_eq!(mm.read(0x2000), 0x22);
+        assert!(switches.get().ramrd);
+
+        // Zero page follows ALTZP instead
+        mm.write(0x80, 0x33);
+        mm.write(0xC009, 0);
+        assert_eq!(mm.read(0x80), 0x00);
+    }
+
+    #[test]
+    fn mmu_80store() {
+        let (mut mm, switches, _, _) = machine();
+        mm.write(0xC001, 0);
+        mm.read(0xC055);
+        mm.write(0x400, 0xC1);
+        mm.read(0xC054);
+        mm.write(0x400, 0xC2);
+        assert_eq!(mm.read(0xC018), 0x80);
+        assert_eq!(mm.read(0xC01C), 0x00);
+
+        let ram = mm.find_by_addr(0x400).unwrap().get_id();
+        let raw = mm.region(ram).unwrap().get_raw();
+        assert_eq!(raw[0x400], 0xC2);
+        assert_eq!(raw[0x10400], 0xC1);
+
+        mm.write(0xC00D, 0);
+        assert!(switches.get().col80);
+        assert_eq!(mm.read(0xC01F), 0x80);
+
+        assert_eq!(mm.read(0xC01E), 0x00);
+        mm.write(0xC00F, 0);
+        assert_eq!(mm.read(0xC01E), 0x80);
+    }
+
+    #[test]
+    fn mmu_display_switches() {
+        let (mut mm, switches, _, _) = machine();
+        assert_eq!(mm.read(0xC01A), 0x80);
+
+        mm.read(0xC050);
+        mm.read(0xC053);
+        mm.write(0xC055, 0);
+        mm.read(0xC057);
+        let s = switches.get();
+        assert!(!s.text && s.mixed && s.page2 && s.hires);
+        assert_eq!(mm.read(0xC01A), 0x00);
+        assert_eq!(mm.read(0xC01B), 0x80);
+        assert_eq!(mm.read(0xC01C), 0x80);
+        assert_eq!(mm.read(0xC01D), 0x80);
+
+        // Status reads leave the switches alone
+        assert_eq!(switches.get(), s);
+    }
+
+    #[test]
+    fn mmu_vblank() {
+        let (mm, _, clock, _) = machine();
+        assert_eq!(mm.read(0xC019), 0x00);
+        clock.set(CYCLES_PER_LINE * 6);
+        assert_eq!(mm.read(0xC019), 0x80);
+        clock.set(CYCLES_PER_LINE * 198);
+        assert_eq!(mm.read(0xC019), 0x00);
+    }
+
+    #[test]
+    fn mmu_keyboard() {
+        let (mut mm, _, _, keyboard) = machine();
+        let mut k = keyboard.get();
+        k.press(b'A');
+        keyboard.set(k);
+        assert_eq!(mm.read(0xC000), 0xC1);
+        assert_eq!(mm.read(0xC00F), 0xC1);
+
+        // Clearing the strobe keeps the code and reports whether a key is held
+        assert_eq!(mm.read(0xC010), 0xC1);
+        assert_eq!(mm.read(0xC000), 0x41);
+        assert_eq!(mm.read(0xC01A), 0xC1);
+        k = keyboard.get();
+        k.press(0x0D);
+        k.down = false;
+        keyboard.set(k);
+        mm.write(0xC010, 0);
+        assert_eq!(mm.read(0xC000), 0x0D);
+        assert_eq!(mm.read(0xC010), 0x0D);
+
+        assert_eq!(mm.read(0xC061), 0x00);
+        keyboard.set(Keyboard {
+            open_apple: true,
+            ..keyboard.get()
+        });
+        assert_eq!(mm.read(0xC061), 0x80);
+        assert_eq!(mm.read(0xC062), 0x00);
+    }
+
+    #[test]
+    fn mmu_peek() {
+        let (mm, switches, _, keyboard) = machine();
+        let mut k = keyboard.get();
+        k.press(b'A');
+        keyboard.set(k);
+
+        // Dumping the I/O page leaves the switches and the keyboard strobe alone
+        let data = tools::dump(&mm, 0xC000, 0xC0FF).unwrap();
+        assert_eq!(data[0x00], 0xC1);
+        assert_eq!(data[0x10], 0xC1);
+        assert_eq!(switches.get(), Switches::default());
+        assert_eq!(keyboard.get().latch, 0xC1);
+    }
+}
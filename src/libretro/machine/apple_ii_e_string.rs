@@ -1,71 +1,130 @@
-#[allow(dead_code)]
+use crate::machine::apple_iie_e_display::{glyph, glyph_char};
+use std::str::FromStr;
+
+/// How a character on the text screen is shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Normal,
+    Inverse,
+    Flash,
+}
+
+/// Character and style shown by screen code `code`
+///
+/// With the alternate character set, $40-$5F are MouseText, which decodes to the
+/// nearest Unicode symbols, and $60-$7F are inverse lowercase instead of flashing.
+pub fn decode(code: u8, altcharset: bool) -> (char, Style) {
+    // Flashing characters are decoded in their normal phase
+    let (c, inverse) = glyph_char(glyph(code, true, altcharset));
+    let style = match code {
+        0x40..=0x7F if !altcharset => Style::Flash,
+        _ if inverse => Style::Inverse,
+        _ => Style::Normal,
+    };
+    (c, style)
+}
+
+/// Screen code that shows `c` in `style`, if the character set has one
+///
+/// Normal uppercase is at both $80-$9F and $C0-$DF, and encodes to $C0-$DF.
+pub fn encode(c: char, style: Style, altcharset: bool) -> Option<u8> {
+    (0..=0xFF)
+        .rev()
+        .find(|&code| decode(code, altcharset) == (c, style))
+}
+
+/// Text along with the screen codes that display it
 pub struct AppleIIeString {
     pub string: String,
-    pub a2string: String,
+    pub a2string: Vec<u8>,
 }
 
-#[allow(dead_code)]
 impl AppleIIeString {
-    pub fn from_str(str: String) -> Self {
-        let mut a2 = String::with_capacity(str.len());
-        for (_, c) in str.char_indices() {
-            let newc = {
-                if c.is_ascii_uppercase() {
-                    let letter = c as u8 - 'A' as u8;
-                    (0x1 + letter) as char
-                } else if c.is_ascii_lowercase() {
-                    let letter = c as u8 - 'a' as u8;
-                    (0x61 + letter) as char
-                } else if c.is_ascii_digit() {
-                    let letter = c as u8 - '0' as u8;
-                    (0x30 + letter) as char
-                } else {
-                    let val = match c {
-                        '[' => 0x1B,
-                        '\\' => 0x1C,
-                        ']' => 0x1D,
-                        '^' => 0x1E,
-                        '_' => 0x1F,
-                        ' ' => 0x20,
-                        '!' => 0x21,
-                        '"' => 0x22,
-                        '#' => 0x23,
-                        '$' => 0x24,
-                        '%' => 0x25,
-                        '&' => 0x26,
-                        '\'' => 0x27,
-                        '(' => 0x28,
-                        ')' => 0x29,
-                        '*' => 0x2A,
-                        '+' => 0x2B,
-                        ',' => 0x2C,
-                        '-' => 0x2D,
-                        '.' => 0x2E,
-                        '/' => 0x2F,
-                        ':' => 0x3A,
-                        ';' => 0x3B,
-                        '<' => 0x3C,
-                        '=' => 0x3D,
-                        '>' => 0x3E,
-                        '?' => 0x3F,
-                        _ => 0x56u8
-                    };
-                    val as char
-                }
-            };
-            a2.push(newc);
-        }
+    /// Encodes every character of `str` in `style`
+    pub fn encode(str: &str, style: Style, altcharset: bool) -> Result<Self, String> {
+        let a2string = str
+            .chars()
+            .map(|c| {
+                encode(c, style, altcharset)
+                    .ok_or_else(|| format!("Cannot show {:?} as {:?} text", c, style))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        Ok(Self {
+            string: str.to_string(),
+            a2string,
+        })
+    }
+
+    /// Decodes screen codes, dropping the style of each character
+    pub fn from_a2(a2: Vec<u8>, altcharset: bool) -> Self {
+        let string = a2.iter().map(|&x| decode(x, altcharset).0).collect();
         Self {
-            string: str,
+            string,
             a2string: a2,
         }
     }
+}
 
-    pub fn from_a2(a2: String) -> Self {
-        let str = String::with_capacity(a2.len());
-        Self {
-            string: str,
-            a2string: a2
+impl FromStr for AppleIIeString {
+    type Err = String;
+
+    /// Encodes `s` as normal text in the primary character set
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::encode(s, Style::Normal, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::apple_ii_e_string::{AppleIIeString, Style, decode, encode};
+
+    #[test]
+    fn a2_string_round_trip() {
+        for altcharset in [false, true] {
+            for code in 0..=0xFF {
+                let (c, style) = decode(code, altcharset);
+                let back = encode(c, style, altcharset).unwrap();
+                let expected = if (0x80..0xA0).contains(&code) {
+                    code + 0x40
+                } else {
+                    code
+                };
+                assert_eq!(back, expected, "{:02X} {}", code, altcharset);
+            }
         }
     }
+
+    #[test]
+    fn a2_string_styles() {
+        assert_eq!(decode(0x01, false), ('A', Style::Inverse));
+        assert_eq!(decode(0x41, false), ('A', Style::Flash));
+        assert_eq!(decode(0x60, false), (' ', Style::Flash));
+        assert_eq!(decode(0xC1, false), ('A', Style::Normal));
+        assert_eq!(decode(0xE1, false), ('a', Style::Normal));
+        assert_eq!(decode(0x41, true), ('○', Style::Normal));
+        assert_eq!(decode(0x61, true), ('a', Style::Inverse));
+
+        // Rubout is printable rather than a control character
+        assert_eq!(decode(0xFF, false), ('░', Style::Normal));
+        assert_eq!(decode(0x7F, true), ('░', Style::Inverse));
+
+        // Lowercase only flashes as symbols, and MouseText needs the alternate set
+        assert_eq!(encode('a', Style::Flash, false), None);
+        assert_eq!(encode('○', Style::Normal, false), None);
+    }
+
+    #[test]
+    fn a2_string_text() {
+        let hello: AppleIIeString = "Hello, world!".parse().unwrap();
+        assert_eq!(hello.a2string[0..2], [0xC8, 0xE5]);
+        assert_eq!(hello.a2string[5], 0xAC);
+        assert_eq!(
+            AppleIIeString::from_a2(hello.a2string, false).string,
+            "Hello, world!"
+        );
+
+        let inverse = AppleIIeString::encode("OK", Style::Inverse, false).unwrap();
+        assert_eq!(inverse.a2string, [0x0F, 0x0B]);
+        assert!("€".parse::<AppleIIeString>().is_err());
+    }
 }
@@ -0,0 +1,408 @@
+/**
+ * Known Apple II ROM database
+ *
+ * Checksums follow the dumps listed in MAME's apple2 and apple2e drivers.
+ * Images are matched on CRC32 first and then confirmed with SHA-1.
+ *
+ * The //c system ROMs (342-0272, 342-0445 and their revisions) are not listed
+ * yet, as their checksums have not been verified against dumps. Only the video
+ * ROM it shares with the Enhanced //e is known for the //c.
+ */
+use log::{Level, log};
+use sha1::{Digest, Sha1};
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AppleModel {
+    AppleII,
+    AppleIIPlus,
+    AppleIIe,
+    AppleIIeEnhanced,
+    AppleIIc,
+}
+
+impl Display for AppleModel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppleModel::AppleII => write!(f, "Apple ]["),
+            AppleModel::AppleIIPlus => write!(f, "Apple ][+"),
+            AppleModel::AppleIIe => write!(f, "Apple //e"),
+            AppleModel::AppleIIeEnhanced => write!(f, "Enhanced Apple //e"),
+            AppleModel::AppleIIc => write!(f, "Apple //c"),
+        }
+    }
+}
+
+pub struct KnownRom {
+    pub part: &'static str,
+    pub description: &'static str,
+    pub models: &'static [AppleModel],
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: &'static str,
+}
+
+use AppleModel::*;
+
+pub const KNOWN_ROMS: &[KnownRom] = &[
+    // Apple ][
+    KnownRom {
+        part: "341-0001-00",
+        description: "Integer BASIC E0",
+        models: &[AppleII],
+        size: 0x800,
+        crc32: 0xc0a4ad3b,
+        sha1: "bf32195efcb34b694c893c2d342321ec3a24b98f",
+    },
+    KnownRom {
+        part: "341-0002-00",
+        description: "Integer BASIC E8",
+        models: &[AppleII],
+        size: 0x800,
+        crc32: 0xa99c2cf6,
+        sha1: "9767d92d04fc65c626223f25564cc47d7f82a7ea",
+    },
+    KnownRom {
+        part: "341-0003-00",
+        description: "Integer BASIC F0",
+        models: &[AppleII],
+        size: 0x800,
+        crc32: 0x62230d38,
+        sha1: "f268022da555e4c809ca1ae9e5d2f00b388ff61c",
+    },
+    KnownRom {
+        part: "341-0004-00",
+        description: "Monitor F8",
+        models: &[AppleII],
+        size: 0x800,
+        crc32: 0x020a86d0,
+        sha1: "52a18bd578a4694420009cad7a7a5779a8c00226",
+    },
+    // Apple ][+
+    KnownRom {
+        part: "341-0011",
+        description: "Applesoft D0",
+        models: &[AppleIIPlus],
+        size: 0x800,
+        crc32: 0x6f05f949,
+        sha1: "0287ebcef2c1ce11dc71be15a99d2d7e0e128b1e",
+    },
+    KnownRom {
+        part: "341-0012",
+        description: "Applesoft D8",
+        models: &[AppleIIPlus],
+        size: 0x800,
+        crc32: 0x1f08087c,
+        sha1: "a75ce5aab6401355bf1ab01b04e4946a424879b5",
+    },
+    KnownRom {
+        part: "341-0013",
+        description: "Applesoft E0",
+        models: &[AppleIIPlus],
+        size: 0x800,
+        crc32: 0x2b8d9a89,
+        sha1: "8d82a1da63224859bd619005fab62c4714b25dd7",
+    },
+    KnownRom {
+        part: "341-0014",
+        description: "Applesoft E8",
+        models: &[AppleIIPlus],
+        size: 0x800,
+        crc32: 0x5719871a,
+        sha1: "37501be96d36d041667c15d63e0c1eff2f7dd4e9",
+    },
+    KnownRom {
+        part: "341-0015",
+        description: "Applesoft F0",
+        models: &[AppleIIPlus],
+        size: 0x800,
+        crc32: 0x9a04eecf,
+        sha1: "e6bf91ed28464f42b807f798fb6422e5948cf581",
+    },
+    KnownRom {
+        part: "341-0020-00",
+        description: "Autostart Monitor F8",
+        models: &[AppleIIPlus],
+        size: 0x800,
+        crc32: 0x079589c4,
+        sha1: "a28852ff997b4790e53d8d0352112c4b1a395098",
+    },
+    KnownRom {
+        part: "341-0036",
+        description: "Video character ROM",
+        models: &[AppleII, AppleIIPlus],
+        size: 0x800,
+        crc32: 0x64f415c6,
+        sha1: "f9d312f128c9557d9d6ac03bfad6c3ddf83e5659",
+    },
+    // Apple //e
+    KnownRom {
+        part: "342-0135-b",
+        description: "CD ROM",
+        models: &[AppleIIe],
+        size: 0x2000,
+        crc32: 0xe248835e,
+        sha1: "523838c19c79f481fdaae5d0d5ccd3f6f25e4ea6",
+    },
+    KnownRom {
+        part: "342-0134-a",
+        description: "EF ROM",
+        models: &[AppleIIe],
+        size: 0x2000,
+        crc32: 0xfc3d59d8,
+        sha1: "8895a4b703f2184b673078f411f4089889b61c54",
+    },
+    KnownRom {
+        part: "342-0133-a",
+        description: "Video character ROM",
+        models: &[AppleIIe],
+        size: 0x1000,
+        crc32: 0xb081df66,
+        sha1: "7060de104046736529c1e8a687a0dd7b84f8c51b",
+    },
+    // Enhanced Apple //e
+    KnownRom {
+        part: "342-0304-a",
+        description: "Enhanced CD ROM",
+        models: &[AppleIIeEnhanced],
+        size: 0x2000,
+        crc32: 0x443aa7c4,
+        sha1: "3aecc56a2134df85e0f1f3ac6e3b5c6bc3a7f4c6",
+    },
+    KnownRom {
+        part: "342-0303-a",
+        description: "Enhanced EF ROM",
+        models: &[AppleIIeEnhanced],
+        size: 0x2000,
+        crc32: 0x95e10034,
+        sha1: "afb09bb96038232dc757d40c0605623cae38088e",
+    },
+    KnownRom {
+        part: "342-0265-a",
+        description: "Enhanced video character ROM",
+        models: &[AppleIIeEnhanced, AppleIIc],
+        size: 0x1000,
+        crc32: 0x2651014d,
+        sha1: "b2b5d87f52693817fc747df087a4aa1ddcdb1f10",
+    },
+];
+
+pub enum RomIdentity {
+    Known(&'static KnownRom),
+    /// The image repeats a known ROM, e.g. a 2K part read from a 4K socket
+    Mirrored(&'static KnownRom),
+    /// Every byte is the same, which usually means the read failed
+    Blank(u8),
+    Unknown,
+}
+
+pub struct RomInfo {
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: String,
+    pub identity: RomIdentity,
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn matches(rom: &KnownRom, data: &[u8]) -> bool {
+    data.len() == rom.size && crc32fast::hash(data) == rom.crc32 && rom.sha1 == sha1_hex(data)
+}
+
+pub fn identify(data: &[u8]) -> RomIdentity {
+    identify_in(data, KNOWN_ROMS)
+}
+
+fn identify_in(data: &[u8], roms: &'static [KnownRom]) -> RomIdentity {
+    if let Some(rom) = roms.iter().find(|rom| matches(rom, data)) {
+        return RomIdentity::Known(rom);
+    }
+
+    if let Some(&first) = data.first()
+        && data.iter().all(|&b| b == first)
+    {
+        return RomIdentity::Blank(first);
+    }
+
+    for rom in roms {
+        if data.len() <= rom.size || !data.len().is_multiple_of(rom.size) {
+            continue;
+        }
+        let head = &data[..rom.size];
+        if data.chunks(rom.size).all(|x| x == head) && matches(rom, head) {
+            return RomIdentity::Mirrored(rom);
+        }
+    }
+
+    RomIdentity::Unknown
+}
+
+/// Identifies a ROM loaded into `model` and logs anything suspicious about it
+///
+/// Only the files the loader expects are checked, so an image that is not in
+/// the database is most likely a bad dump of one that is.
+pub fn check(name: &str, data: &[u8], model: AppleModel) {
+    let (level, msg) = report(name, &identify(data), model);
+    log!(level, "{}", msg);
+}
+
+/// What to say about a ROM identified as `identity`, and how loudly
+fn report(name: &str, identity: &RomIdentity, model: AppleModel) -> (Level, String) {
+    match identity {
+        RomIdentity::Known(rom) | RomIdentity::Mirrored(rom) if !rom.models.contains(&model) => (
+            Level::Warn,
+            format!(
+                "{} is {} ({}), which is not a part for the {}",
+                name, rom.part, rom.description, model
+            ),
+        ),
+        RomIdentity::Known(rom) => (
+            Level::Info,
+            format!("{} is {} ({})", name, rom.part, rom.description),
+        ),
+        RomIdentity::Mirrored(rom) => (
+            Level::Warn,
+            format!(
+                "{} is an overdump of {} ({}), expected {:#X} bytes",
+                name, rom.part, rom.description, rom.size
+            ),
+        ),
+        RomIdentity::Blank(b) => (
+            Level::Warn,
+            format!("{} is blank (all ${:02X}), likely a bad dump", name, b),
+        ),
+        RomIdentity::Unknown => (
+            Level::Warn,
+            format!("{} is not in the ROM database, it may be a bad dump", name),
+        ),
+    }
+}
+
+impl RomInfo {
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            size: data.len(),
+            crc32: crc32fast::hash(data),
+            sha1: sha1_hex(data),
+            identity: identify(data),
+        }
+    }
+}
+
+impl Display for KnownRom {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let models: Vec<String> = self.models.iter().map(|x| x.to_string()).collect();
+        write!(
+            f,
+            "{} {} ({})",
+            self.part,
+            self.description,
+            models.join(", ")
+        )
+    }
+}
+
+impl Display for RomInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Size:  {:#X} ({} bytes)", self.size, self.size)?;
+        writeln!(f, "CRC32: {:08x}", self.crc32)?;
+        writeln!(f, "SHA-1: {}", self.sha1)?;
+        match self.identity {
+            RomIdentity::Known(rom) => write!(f, "ROM:   {}", rom),
+            RomIdentity::Mirrored(rom) => write!(f, "ROM:   {} (overdump)", rom),
+            RomIdentity::Blank(b) => write!(f, "ROM:   Blank (all ${:02X}), likely a bad dump", b),
+            RomIdentity::Unknown => write!(f, "ROM:   Unknown"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::apple_ii_roms::{
+        AppleModel, KnownRom, RomIdentity, RomInfo, identify_in, report, sha1_hex,
+    };
+    use log::Level;
+
+    /// A table holding one ROM with the contents `data`
+    fn table(data: &[u8], models: &'static [AppleModel]) -> &'static [KnownRom] {
+        let rom = KnownRom {
+            part: "342-0000-a",
+            description: "Test ROM",
+            models,
+            size: data.len(),
+            crc32: crc32fast::hash(data),
+            sha1: sha1_hex(data).leak(),
+        };
+        Box::leak(Box::new([rom]))
+    }
+
+    #[test]
+    fn rom_info_checksums() {
+        let info = RomInfo::new(b"123456789");
+        assert_eq!(info.size, 9);
+        assert_eq!(info.crc32, 0xcbf43926);
+        assert_eq!(info.sha1, "f7c3bc1d808e04732adf679965ccc34ca7ae3441");
+        assert!(matches!(info.identity, RomIdentity::Unknown));
+    }
+
+    #[test]
+    fn rom_info_blank() {
+        let info = RomInfo::new(&[0xFF; 0x800]);
+        assert!(matches!(info.identity, RomIdentity::Blank(0xFF)));
+    }
+
+    #[test]
+    fn rom_identify() {
+        let data: Vec<u8> = (0..=0xFF).collect();
+        let roms = table(&data, &[AppleModel::AppleIIe]);
+        assert!(
+            matches!(identify_in(&data, roms), RomIdentity::Known(rom) if rom.part == "342-0000-a")
+        );
+        assert!(matches!(
+            identify_in(&[data.clone(), data.clone()].concat(), roms),
+            RomIdentity::Mirrored(_)
+        ));
+
+        // A single changed byte fails both checksums
+        let mut bad = data.clone();
+        bad[0x80] ^= 1;
+        assert!(matches!(identify_in(&bad, roms), RomIdentity::Unknown));
+    }
+
+    #[test]
+    fn rom_wrong_model() {
+        let data: Vec<u8> = (0..=0xFF).collect();
+        let roms = table(&data, &[AppleModel::AppleIIeEnhanced]);
+        let identity = identify_in(&data, roms);
+
+        let (level, msg) = report("apple2e_cd.bin", &identity, AppleModel::AppleIIe);
+        assert_eq!(level, Level::Warn);
+        assert_eq!(
+            msg,
+            "apple2e_cd.bin is 342-0000-a (Test ROM), which is not a part for the Apple //e"
+        );
+        let (level, _) = report("apple2e_cd.bin", &identity, AppleModel::AppleIIeEnhanced);
+        assert_eq!(level, Level::Info);
+    }
+
+    #[test]
+    fn rom_bad_dump() {
+        let data: Vec<u8> = (0..=0xFF).collect();
+        let roms = table(&data, &[AppleModel::AppleIIe]);
+        let mut bad = data.clone();
+        bad[0x80] ^= 1;
+        let identity = identify_in(&bad, roms);
+
+        let (level, msg) = report("apple2e_video.bin", &identity, AppleModel::AppleIIe);
+        assert_eq!(level, Level::Warn);
+        assert_eq!(
+            msg,
+            "apple2e_video.bin is not in the ROM database, it may be a bad dump"
+        );
+    }
+}
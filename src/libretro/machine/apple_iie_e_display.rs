@@ -1,8 +1,224 @@
-use crate::memory::{Endian, Memory, ROM};
+use crate::machine::apple_ii_e_mmu::Switches;
+use crate::machine::apple_ii_roms::{self, AppleModel};
+use crate::machine::monitor::Palette;
+use crate::machine::ntsc::NtscFilter;
+use crate::machine::video_scanner::{VISIBLE_COLUMNS, VISIBLE_LINES};
+use crate::memory::{Endian, Memory, ROM, RomError};
+use std::fmt::{Display as FmtDisplay, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
 
-pub const DISPLAY_WIDTH: usize = 320;
-pub const DISPLAY_HEIGHT: usize = 200;
-pub const DISPLAY_SCALE: u32 = 3;
+/// Wide enough for 80 columns, with each scanline drawn twice
+///
+/// Doubling the 192 lines keeps dots close to square at 560 wide, so frames
+/// and screenshots keep the //e's shape at any scale, and leaves a row between
+/// lines for the monitor's scanline gaps.
+pub const DISPLAY_WIDTH: usize = 560;
+pub const DISPLAY_HEIGHT: usize = 384;
+pub const DISPLAY_SCALE: u32 = 2;
+
+/// Frames between each toggle of flashing characters, giving about 1.9 Hz
+pub const FLASH_FRAMES: usize = 16;
+
+const TEXT_PAGE_1: usize = 0x400;
+const TEXT_PAGE_2: usize = 0x800;
+const HIRES_PAGE_1: usize = 0x2000;
+const HIRES_PAGE_2: usize = 0x4000;
+const CHAR_WIDTH: usize = 7;
+const CHAR_HEIGHT: usize = 8;
+const DOTS: usize = 560;
+
+/// Address of the first character of text row `row`
+pub fn text_row_address(page: usize, row: usize) -> usize {
+    page + (row % 8) * 0x80 + (row / 8) * 0x28
+}
+
+/// Address of the first byte of hi-res line `line`
+pub fn hires_line_address(page: usize, line: usize) -> usize {
+    page + (line % 8) * 0x400 + ((line / 8) % 8) * 0x80 + (line / 64) * 0x28
+}
+
+/// Artifact colour of a lone hi-res dot, as a lo-res colour
+fn artifact_color(x: usize, high: bool) -> u8 {
+    match (x % 2, high) {
+        (0, false) => 3,  // Purple
+        (0, true) => 6,   // Medium blue
+        (_, false) => 12, // Green
+        (_, true) => 9,   // Orange
+    }
+}
+
+/// Lo-res colours of the 560 half dots of a 40 byte hi-res line
+///
+/// Adjacent lit dots are white, a lone dot takes its column's artifact colour and
+/// an unlit dot between two lit ones is filled with their colour. Setting the high
+/// bit of a byte delays its dots by half a dot, which shifts the palette.
+pub fn hires_line(data: &[u8], monochrome: bool) -> [u8; 560] {
+    let dot = |x: usize| x < 280 && data[x / 7] & (1 << (x % 7)) != 0;
+    let high = |x: usize| data[x / 7] & 0x80 != 0;
+
+    let mut line = [0u8; 560];
+    for x in 0..280 {
+        let on = dot(x);
+        let left = x > 0 && dot(x - 1);
+        let right = dot(x + 1);
+        let color = match (on, left, right) {
+            (true, _, _) if monochrome => 15,
+            (true, false, false) => artifact_color(x, high(x)),
+            (true, _, _) => 15,
+            (false, true, true) if !monochrome => artifact_color(x - 1, high(x - 1)),
+            (false, _, _) => 0,
+        };
+        let start = 2 * x + high(x) as usize;
+        for half in line.iter_mut().skip(start).take(2) {
+            *half = color;
+        }
+    }
+    line
+}
+
+/// Lo-res colours of the 560 dots of a double hi-res line
+///
+/// Dots alternate between 7 from an auxiliary byte and 7 from a main byte. Each
+/// group of four dots shows the colour whose bits they spell, first dot lowest.
+pub fn double_hires_line(aux: &[u8], main: &[u8], monochrome: bool) -> [u8; 560] {
+    let dot = |x: usize| {
+        let byte = x / 7;
+        let data = if byte.is_multiple_of(2) {
+            aux[byte / 2]
+        } else {
+            main[byte / 2]
+        };
+        data & (1 << (x % 7)) != 0
+    };
+
+    let mut line = [0u8; 560];
+    for (group, dots) in line.chunks_mut(4).enumerate() {
+        let color = (0..4).fold(0, |c, i| c | (dot(group * 4 + i) as u8) << i);
+        for (i, half) in dots.iter_mut().enumerate() {
+            *half = match monochrome {
+                true if color & (1 << i) != 0 => 15,
+                true => 0,
+                false => color,
+            };
+        }
+    }
+    line
+}
+
+/// Rotates a double lo-res colour from auxiliary memory into the main memory palette
+pub fn aux_lores_color(color: u8) -> u8 {
+    ((color << 1) | (color >> 3)) & 0xF
+}
+
+/// Character generator entry used to draw screen code `code`, and whether to
+/// invert it
+///
+/// The video ROM holds inverse characters at $00-$3F and normal characters at
+/// $80-$FF. With the primary character set, codes $40-$7F flash between the two.
+/// The alternate set shows MouseText at $40-$5F, from the enhanced ROM, and
+/// inverse lowercase at $60-$7F. The original ROM repeats inverse uppercase at
+/// $40-$5F instead, which `TextScreen::read` accounts for.
+pub fn glyph(code: u8, flash: bool, altcharset: bool) -> (u8, bool) {
+    match code {
+        0x40..=0x7F if !altcharset && flash => ((code & 0x3F) | 0x80, false),
+        0x40..=0x7F if !altcharset => (code & 0x3F, false),
+        0x60..=0x7F => (code | 0x80, true),
+        _ => (code, false),
+    }
+}
+
+/// MouseText as the nearest Unicode symbols, from closed apple at $40 to the left bar at $5F
+const MOUSETEXT: [char; 32] = [
+    '●', '○', '↖', '⧗', '✓', '✔', '♟', '♙', '←', '…', '↓', '↑', '▔', '↵', '█', '⇤', '⇥', '⤓', '⤒',
+    '─', '└', '→', '▒', '▓', '⊏', '⊐', '▕', '◆', '═', '┼', '▯', '▏',
+];
+
+/// Character drawn by character generator entry `index`, and whether it is inverse
+///
+/// Takes the result of `glyph`, so the flash state has already been applied. The
+/// rubout at $FF is a light shade, as MouseText already has the checkerboard.
+pub fn glyph_char(glyph: (u8, bool)) -> (char, bool) {
+    let (index, inverse) = glyph;
+    let low = index & 0x7F;
+    let c = match low {
+        0x00..=0x1F => (low + 0x40) as char,
+        0x40..=0x5F if index < 0x80 => MOUSETEXT[low as usize - 0x40],
+        0x7F => '░',
+        _ => low as char,
+    };
+    (c, inverse || index < 0x40)
+}
+
+/// Screen codes of the text page, 40 or 80 to a row
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextScreen {
+    pub rows: Vec<Vec<u8>>,
+    pub altcharset: bool,
+    pub flash: bool,
+}
+
+impl TextScreen {
+    /// Reads the displayed text page, taking each 80 column character from
+    /// auxiliary memory and then main memory
+    ///
+    /// Rows showing graphics are blank, so mixed mode only has the bottom four.
+    /// Without `mousetext`, alternate set codes $40-$5F are read as the inverse
+    /// uppercase the original video ROM shows there.
+    pub fn read(main: &[u8], aux: &[u8], switches: Switches, flash: bool, mousetext: bool) -> Self {
+        let page = if switches.page2 && !switches.store80 {
+            TEXT_PAGE_2
+        } else {
+            TEXT_PAGE_1
+        };
+        let width = if switches.col80 { 80 } else { 40 };
+        let shown = |code: u8| match code {
+            0x40..=0x5F if switches.altcharset && !mousetext => code & 0x1F,
+            _ => code,
+        };
+        let rows = (0..24)
+            .map(|row| {
+                if !switches.text && (!switches.mixed || row < 20) {
+                    return vec![0xA0; width];
+                }
+                let base = text_row_address(page, row);
+                let mut codes = Vec::with_capacity(width);
+                for addr in base..base + 40 {
+                    if switches.col80 {
+                        codes.push(shown(aux[addr]));
+                    }
+                    codes.push(shown(main[addr]));
+                }
+                codes
+            })
+            .collect();
+        Self {
+            rows,
+            altcharset: switches.altcharset,
+            flash,
+        }
+    }
+
+    /// Draws the screen at the top of a terminal with ANSI escapes, using
+    /// reverse video for inverse characters
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for (row, codes) in self.rows.iter().enumerate() {
+            out.push_str(&format!("\x1b[{};1H", row + 1));
+            let mut reverse = false;
+            for &code in codes {
+                let (c, inverse) = glyph_char(glyph(code, self.flash, self.altcharset));
+                if inverse != reverse {
+                    out.push_str(if inverse { "\x1b[7m" } else { "\x1b[27m" });
+                    reverse = inverse;
+                }
+                out.push(c);
+            }
+            out.push_str("\x1b[0m\x1b[K");
+        }
+        out
+    }
+}
 
 struct Color {
     r: u8,
@@ -11,7 +227,7 @@ struct Color {
 }
 
 impl Color {
-    fn new(r: u8, g: u8, b: u8) -> Self {
+    const fn new(r: u8, g: u8, b: u8) -> Self {
         Self { r, g, b }
     }
     fn to_slice(&self) -> [u8; 4] {
@@ -19,6 +235,32 @@ impl Color {
     }
 }
 
+/// The 16 lo-res colours
+const LORES_COLORS: [Color; 16] = [
+    Color::new(0, 0, 0),       // Black
+    Color::new(227, 30, 96),   // Magenta
+    Color::new(96, 78, 189),   // Dark blue
+    Color::new(255, 68, 253),  // Purple
+    Color::new(0, 163, 96),    // Dark green
+    Color::new(156, 156, 156), // Grey 1
+    Color::new(20, 207, 253),  // Medium blue
+    Color::new(208, 195, 255), // Light blue
+    Color::new(96, 114, 3),    // Brown
+    Color::new(255, 106, 60),  // Orange
+    Color::new(156, 156, 156), // Grey 2
+    Color::new(255, 160, 208), // Pink
+    Color::new(20, 245, 60),   // Green
+    Color::new(208, 221, 141), // Yellow
+    Color::new(114, 255, 208), // Aqua
+    Color::new(255, 255, 255), // White
+];
+
+/// RGB of lo-res colour `color` in the built-in palette
+pub fn lores_rgb(color: u8) -> [u8; 3] {
+    let c = &LORES_COLORS[color as usize & 0xF];
+    [c.r, c.g, c.b]
+}
+
 struct Position {
     pub x: usize,
     pub y: usize,
@@ -34,52 +276,409 @@ impl Position {
     }
 }
 
+/// How scanlines are turned into pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Renderer {
+    /// Idealised colours straight from the palette
+    Rgb,
+    /// Decode the composite signal like a monitor
+    Ntsc(NtscFilter),
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    /// Parses `rgb`, `ntsc` or `ntsc:<luma width>,<chroma width>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "rgb" => Ok(Renderer::Rgb),
+            None if s == "ntsc" => Ok(Renderer::Ntsc(NtscFilter::default())),
+            Some(("ntsc", filter)) => Ok(Renderer::Ntsc(filter.parse()?)),
+            _ => Err(format!("Invalid renderer: {}", s)),
+        }
+    }
+}
+
+impl FmtDisplay for Renderer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Renderer::Rgb => write!(f, "rgb"),
+            Renderer::Ntsc(x) => write!(f, "ntsc:{},{}", x.luma_width, x.chroma_width),
+        }
+    }
+}
+
 pub struct Display {
     rom: ROM,
+    renderer: Renderer,
+    /// Draw hi-res as white dots instead of artifact colour
+    monochrome: bool,
+    /// Colours of the RGB renderer
+    palette: Palette,
+    /// Whether the alternate character set has MouseText, as on the enhanced models
+    mousetext: bool,
 }
 
 impl Display {
-    pub fn new() -> Self {
-        Self {
-            rom: ROM::new(0x1000, Endian::Little, "apple2e_video.bin"),
-        }
+    pub fn new(
+        rom_paths: &[PathBuf],
+        model: AppleModel,
+        renderer: Renderer,
+    ) -> Result<Self, RomError> {
+        let rom = ROM::new(0x1000, Endian::Little, "apple2e_video.bin", rom_paths)?;
+        apple_ii_roms::check("apple2e_video.bin", rom.get_raw(), model);
+        Ok(Self {
+            rom,
+            renderer,
+            monochrome: false,
+            palette: Palette::default(),
+            mousetext: matches!(model, AppleModel::AppleIIeEnhanced | AppleModel::AppleIIc),
+        })
+    }
+
+    pub fn has_mousetext(&self) -> bool {
+        self.mousetext
+    }
+
+    pub fn set_monochrome(&mut self, monochrome: bool) {
+        self.monochrome = monochrome;
+    }
+
+    /// Replaces the built-in colours, which the NTSC renderer does not use
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
     }
 
+    /// Sets a pixel of the 560x192 screen
     fn draw_pixel(&self, frame: &mut [u8], color: &Color, pos: Position) {
         let (pixels, _) = frame.as_chunks_mut::<4>();
-        pixels[pos.to_index(DISPLAY_WIDTH)].copy_from_slice(&color.to_slice());
+        for y in [pos.y * 2, pos.y * 2 + 1] {
+            pixels[Position::new(pos.x, y).to_index(DISPLAY_WIDTH)]
+                .copy_from_slice(&color.to_slice());
+        }
+    }
+
+    /// Dots of one line of a character, where set bits in the video ROM are background
+    fn char_dots(&self, glyph: (u8, bool), line: usize) -> u8 {
+        let (index, inverse) = glyph;
+        let row = self
+            .rom
+            .read(index as usize * CHAR_HEIGHT + line % CHAR_HEIGHT);
+        !row ^ if inverse { 0x7F } else { 0 }
     }
 
-    fn draw_char(&self, frame: &mut [u8], c: char, pos: Position) {
-        let color = Color::new(250, 250, 250);
-        let black = Color::new(0, 0, 0);
+    /// Lo-res colours of the 560 dots of scanline `line`
+    ///
+    /// In 80 columns each character or lo-res byte from auxiliary memory is followed
+    /// by the one at the same address in main memory. Double lo-res and double
+    /// hi-res also need AN3 off. Mixed mode shows text in the bottom four rows.
+    /// Text and hi-res dots are white, so `monochrome` gives the raw dot pattern.
+    fn scanline(
+        &self,
+        line: usize,
+        mem: (&[u8], &[u8]),
+        switches: Switches,
+        flash: bool,
+        monochrome: bool,
+    ) -> [u8; DOTS] {
+        let (main, aux) = mem;
+        let page2 = switches.page2 && !switches.store80;
+        let double = switches.col80 && switches.dhires;
+        let row = line / CHAR_HEIGHT;
+        let graphics = !switches.text && (!switches.mixed || row < 20);
 
-        let offset = c as usize;
-        for i in 0..8usize {
-            let row = self.rom.read(offset * 8 + i);
-            for j in 0..8usize {
-                if (row & (1 << j) as u8) != 0 {
-                    self.draw_pixel(frame, &color, Position::new(j + pos.x * 8, i + pos.y * 8));
+        if graphics && switches.hires {
+            let page = if page2 { HIRES_PAGE_2 } else { HIRES_PAGE_1 };
+            let addr = hires_line_address(page, line);
+            let range = addr..addr + 40;
+            return if double {
+                double_hires_line(&aux[range.clone()], &main[range], monochrome)
+            } else {
+                hires_line(&main[range], monochrome)
+            };
+        }
+
+        let page = if page2 { TEXT_PAGE_2 } else { TEXT_PAGE_1 };
+        let base = text_row_address(page, row);
+        let mut dots = [0u8; DOTS];
+        // Each memory byte is 7 dots wide in 80 columns and 14 in 40 columns
+        let (bytes, width): (Vec<(u8, bool)>, usize) = if switches.col80 && (!graphics || double) {
+            let bytes =
+                (0..40).flat_map(|col| [(aux[base + col], true), (main[base + col], false)]);
+            (bytes.collect(), CHAR_WIDTH)
+        } else {
+            let bytes = (0..40).map(|col| (main[base + col], false));
+            (bytes.collect(), CHAR_WIDTH * 2)
+        };
+        for (i, (data, is_aux)) in bytes.into_iter().enumerate() {
+            let x = i * width;
+            if graphics {
+                let nibble = if line % CHAR_HEIGHT < CHAR_HEIGHT / 2 {
+                    data & 0xF
+                } else {
+                    data >> 4
+                };
+                let color = if is_aux {
+                    aux_lores_color(nibble)
                 } else {
-                    self.draw_pixel(frame, &black, Position::new(j + pos.x * 8, i + pos.y * 8));
+                    nibble
+                };
+                dots[x..x + width].fill(color);
+            } else {
+                let bits = self.char_dots(glyph(data, flash, switches.altcharset), line);
+                for (j, dot) in dots[x..x + width].iter_mut().enumerate() {
+                    if bits & (1 << (j * CHAR_WIDTH / width)) != 0 {
+                        *dot = 15;
+                    }
                 }
             }
         }
+        dots
     }
 
-    pub fn write_text(&self, frame: &mut [u8], addr: usize, data: char) {
-        let group = addr / 0x80;
-        let rem = addr % 0x80;
-        if rem > 0x78 {
-            return;
+    /// Draws scanline `line` with the switches as they were when the beam reached each column
+    pub fn render_line(
+        &self,
+        frame: &mut [u8],
+        line: usize,
+        mem: (&[u8], &[u8]),
+        switches: &[Switches; VISIBLE_COLUMNS],
+        flash: bool,
+    ) {
+        let monochrome = self.monochrome || matches!(self.renderer, Renderer::Ntsc(_));
+        let mut dots = self.scanline(line, mem, switches[0], flash, monochrome);
+        for col in 1..VISIBLE_COLUMNS {
+            if switches[col] != switches[col - 1] {
+                let x = col * DOTS / VISIBLE_COLUMNS;
+                let rest = self.scanline(line, mem, switches[col], flash, monochrome);
+                dots[x..].copy_from_slice(&rest[x..]);
+            }
+        }
+
+        match &self.renderer {
+            Renderer::Rgb => {
+                for (x, color) in dots.into_iter().enumerate() {
+                    let [r, g, b] = self.palette.0[color as usize];
+                    self.draw_pixel(frame, &Color::new(r, g, b), Position::new(x, line));
+                }
+            }
+            Renderer::Ntsc(filter) => {
+                let signal: Vec<bool> = dots
+                    .iter()
+                    .enumerate()
+                    .map(|(x, color)| (color >> (x % 4)) & 1 != 0)
+                    .collect();
+                for (x, [r, g, b]) in filter.decode(&signal).into_iter().enumerate() {
+                    self.draw_pixel(frame, &Color::new(r, g, b), Position::new(x, line));
+                }
+            }
         }
+    }
 
-        let col = rem % 0x28;
-        let group2 = rem / 0x28;
-        let row = group2 * 8 + group;
-        if row >= 24 {
-            return;
+    /// Draws the current display page
+    pub fn render(
+        &self,
+        frame: &mut [u8],
+        main: &[u8],
+        aux: &[u8],
+        switches: Switches,
+        flash: bool,
+    ) {
+        for line in 0..VISIBLE_LINES {
+            self.render_line(
+                frame,
+                line,
+                (main, aux),
+                &[switches; VISIBLE_COLUMNS],
+                flash,
+            );
         }
-        self.draw_char(frame, data, Position::new(col, group2 * 8 + group));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::apple_ii_e_mmu::Switches;
+    use crate::machine::apple_iie_e_display::{
+        DISPLAY_HEIGHT, DISPLAY_WIDTH, Display, Renderer, TextScreen, aux_lores_color,
+        double_hires_line, glyph, glyph_char, hires_line, hires_line_address, text_row_address,
+    };
+    use crate::machine::monitor::Palette;
+    use crate::machine::ntsc::NtscFilter;
+    use crate::memory::{Endian, ROM};
+
+    #[test]
+    fn text_rows() {
+        assert_eq!(text_row_address(0x400, 0), 0x400);
+        assert_eq!(text_row_address(0x400, 1), 0x480);
+        assert_eq!(text_row_address(0x400, 8), 0x428);
+        assert_eq!(text_row_address(0x400, 23), 0x7D0);
+        assert_eq!(text_row_address(0x800, 0), 0x800);
+    }
+
+    #[test]
+    fn text_glyphs() {
+        // Inverse and normal characters ignore the flash state
+        assert_eq!(glyph(0x01, false, false), (0x01, false));
+        assert_eq!(glyph(0x01, true, false), (0x01, false));
+        assert_eq!(glyph(0xC1, true, false), (0xC1, false));
+
+        // Flashing 'A' alternates between inverse and normal
+        assert_eq!(glyph(0x41, false, false), (0x01, false));
+        assert_eq!(glyph(0x41, true, false), (0x81, false));
+        assert_eq!(glyph(0x60, true, false), (0xA0, false));
+
+        // The alternate set has MouseText and inverse lowercase instead
+        assert_eq!(glyph(0x41, true, true), (0x41, false));
+        assert_eq!(glyph(0x61, true, true), (0xE1, true));
+        assert_eq!(glyph(0x01, false, true), (0x01, false));
+    }
+
+    #[test]
+    fn text_screen_ansi() {
+        let mut main = vec![0xA0; 0x10000];
+        let mut aux = vec![0xA0; 0x10000];
+        main[0x400] = 0xC8;
+        main[0x401] = 0x09;
+        aux[0x400] = 0x41;
+        let switches = Switches {
+            col80: true,
+            altcharset: true,
+            ..Switches::default()
+        };
+        let screen = TextScreen::read(&main, &aux, switches, false, true);
+        assert_eq!(screen.rows.len(), 24);
+        assert_eq!(screen.rows[0][0..3], [0x41, 0xC8, 0xA0]);
+        assert_eq!(screen.rows[23].len(), 80);
+
+        // MouseText open apple, a normal 'H' and an inverse 'I'
+        let ansi = screen.to_ansi();
+        assert!(ansi.starts_with("\x1b[1;1H○H \x1b[7mI\x1b[27m "));
+        assert!(ansi.contains("\x1b[24;1H"));
+
+        assert_eq!(glyph_char(glyph(0xE1, false, false)), ('a', false));
+        assert_eq!(glyph_char(glyph(0x20, false, false)), (' ', true));
+
+        // The original ROM has inverse uppercase where MouseText would be
+        let screen = TextScreen::read(&main, &aux, switches, false, false);
+        assert_eq!(screen.rows[0][0..3], [0x01, 0xC8, 0xA0]);
+        assert!(screen.to_ansi().starts_with("\x1b[1;1H\x1b[7mA\x1b[27mH"));
+
+        // Graphics hide the text, except for the bottom four rows in mixed mode
+        main[0x7D0] = 0xC1;
+        let graphics = Switches {
+            text: false,
+            col80: false,
+            ..switches
+        };
+        let screen = TextScreen::read(&main, &aux, graphics, false, true);
+        assert_eq!(screen.rows[0], [0xA0; 40]);
+        assert_eq!(screen.rows[23], [0xA0; 40]);
+        let mixed = Switches {
+            mixed: true,
+            ..graphics
+        };
+        let screen = TextScreen::read(&main, &aux, mixed, false, true);
+        assert_eq!(screen.rows[19], [0xA0; 40]);
+        assert_eq!(screen.rows[23][0], 0xC1);
+    }
+
+    #[test]
+    fn double_lores_colors() {
+        assert_eq!(aux_lores_color(0x0), 0x0);
+        assert_eq!(aux_lores_color(0x1), 0x2);
+        assert_eq!(aux_lores_color(0x8), 0x1);
+        assert_eq!(aux_lores_color(0x9), 0x3);
+        assert_eq!(aux_lores_color(0xF), 0xF);
+    }
+
+    #[test]
+    fn hires_rows() {
+        assert_eq!(hires_line_address(0x2000, 0), 0x2000);
+        assert_eq!(hires_line_address(0x2000, 1), 0x2400);
+        assert_eq!(hires_line_address(0x2000, 8), 0x2080);
+        assert_eq!(hires_line_address(0x2000, 64), 0x2028);
+        assert_eq!(hires_line_address(0x4000, 191), 0x5FD0);
+    }
+
+    #[test]
+    fn hires_artifacts() {
+        let mut data = [0u8; 40];
+
+        // Lone dots in even and odd columns
+        data[0] = 0b0000_0101;
+        let line = hires_line(&data, false);
+        assert_eq!(line[0..6], [3, 3, 3, 3, 3, 3]);
+        assert_eq!(line[6..8], [0, 0]);
+
+        // Adjacent dots merge to white
+        data[0] = 0b0000_0011;
+        let line = hires_line(&data, false);
+        assert_eq!(line[0..4], [15, 15, 15, 15]);
+
+        // The high bit shifts by half a dot and swaps the palette
+        data[0] = 0b1000_0010;
+        let line = hires_line(&data, false);
+        assert_eq!(line[2..5], [0, 9, 9]);
+
+        let line = hires_line(&data, true);
+        assert_eq!(line[2..5], [0, 15, 15]);
+    }
+
+    #[test]
+    fn double_hires_colors() {
+        let mut aux = [0u8; 40];
+        let mut main = [0u8; 40];
+
+        // Dots 0-3 spell 0b1001 (orange), dots 4-7 straddle aux and main
+        aux[0] = 0b0001_1001;
+        main[0] = 0b0000_0001;
+        let line = double_hires_line(&aux, &main, false);
+        assert_eq!(line[0..4], [9, 9, 9, 9]);
+        assert_eq!(line[4..8], [9, 9, 9, 9]);
+        assert_eq!(line[8..12], [0, 0, 0, 0]);
+
+        let line = double_hires_line(&aux, &main, true);
+        assert_eq!(line[0..8], [15, 0, 0, 15, 15, 0, 0, 15]);
+    }
+
+    #[test]
+    fn lores_palette() {
+        let mut palette = Palette::default();
+        palette.0[5] = [1, 2, 3];
+        palette.0[10] = [4, 5, 6];
+        let display = Display {
+            rom: ROM::from_bytes(Endian::Little, vec![0; 0x1000]),
+            renderer: Renderer::Rgb,
+            monochrome: false,
+            palette,
+            mousetext: true,
+        };
+
+        // The two greys are the same colour built in but can differ in a palette
+        let mut main = vec![0; 0x10000];
+        main[0x400] = 0x55;
+        main[0x401] = 0xAA;
+        let lores = Switches {
+            text: false,
+            ..Switches::default()
+        };
+        let mut frame = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+        display.render(&mut frame, &main, &main, lores, false);
+        assert_eq!(frame[0..3], [1, 2, 3]);
+        assert_eq!(frame[14 * 4..14 * 4 + 3], [4, 5, 6]);
+    }
+
+    #[test]
+    fn renderer_parse() {
+        assert_eq!("rgb".parse::<Renderer>(), Ok(Renderer::Rgb));
+        assert_eq!(
+            "ntsc".parse::<Renderer>(),
+            Ok(Renderer::Ntsc(NtscFilter::default()))
+        );
+        let renderer: Renderer = "ntsc:2,12".parse().unwrap();
+        assert_eq!(renderer.to_string(), "ntsc:2,12");
+        assert!("ntsc:2".parse::<Renderer>().is_err());
+        assert!("vga".parse::<Renderer>().is_err());
     }
 }
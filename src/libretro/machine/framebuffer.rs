@@ -0,0 +1,87 @@
+/**
+ * In-memory framebuffer
+ *
+ * Machines render into a plain RGBA buffer so frames can be shown in a window,
+ * saved as screenshots or recorded without a window or GPU.
+ */
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// RGBA bytes, row by row
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+
+    pub fn write_png(&self, w: impl Write) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        writer.finish()
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        self.write_png(file).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::framebuffer::Framebuffer;
+
+    #[test]
+    fn framebuffer_png() {
+        let mut fb = Framebuffer::new(4, 2);
+        fb.data_mut()[4..8].copy_from_slice(&[0xFF, 0x80, 0x00, 0xFF]);
+        assert_eq!(fb.pixel(1, 0), [0xFF, 0x80, 0x00, 0xFF]);
+        assert_eq!(fb.pixel(0, 1), [0, 0, 0, 0]);
+
+        let mut png = Vec::new();
+        fb.write_png(&mut png).unwrap();
+        let decoder = png::Decoder::new(std::io::Cursor::new(png));
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut data).unwrap();
+        assert_eq!(data, fb.data());
+    }
+}
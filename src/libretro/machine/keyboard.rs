@@ -0,0 +1,81 @@
+/// Host key that has an Apple //e equivalent
+///
+/// Displays convert their window library's keys to these, so the mapping to
+/// //e codes does not depend on any one library.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostKey {
+    /// Key typing a character, given as typed without Control but with Shift
+    Char(char),
+    Space,
+    Enter,
+    Tab,
+    Escape,
+    Delete,
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Apple //e code for a key, given the character it types without Control
+///
+/// Backspace moves left as on the //e, where the Delete key sends $7F.
+pub fn apple_key(key: HostKey, control: bool) -> Option<u8> {
+    let code = match key {
+        HostKey::Char(c) if c.is_ascii() => c as u8,
+        HostKey::Char(_) => return None,
+        HostKey::Space => b' ',
+        HostKey::Enter => 0x0D,
+        HostKey::Tab => 0x09,
+        HostKey::Escape => 0x1B,
+        HostKey::Delete => 0x7F,
+        HostKey::Backspace | HostKey::Left => 0x08,
+        HostKey::Right => 0x15,
+        HostKey::Up => 0x0B,
+        HostKey::Down => 0x0A,
+    };
+    match code {
+        b'@'..=b'_' | b'a'..=b'z' if control => Some(code & 0x1F),
+        _ => Some(code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::keyboard::{HostKey, apple_key};
+
+    #[test]
+    fn keyboard_control() {
+        assert_eq!(apple_key(HostKey::Char('c'), true), Some(0x03));
+        assert_eq!(apple_key(HostKey::Char('C'), true), Some(0x03));
+        assert_eq!(apple_key(HostKey::Char('@'), true), Some(0x00));
+        assert_eq!(apple_key(HostKey::Char('_'), true), Some(0x1F));
+
+        // Digits and punctuation outside $40-$5F are not changed by Control
+        assert_eq!(apple_key(HostKey::Char('1'), true), Some(b'1'));
+        assert_eq!(apple_key(HostKey::Space, true), Some(b' '));
+        assert_eq!(apple_key(HostKey::Enter, true), Some(0x0D));
+    }
+
+    #[test]
+    fn keyboard_shift() {
+        // Shift is already applied to the character the host reports
+        assert_eq!(apple_key(HostKey::Char('a'), false), Some(b'a'));
+        assert_eq!(apple_key(HostKey::Char('A'), false), Some(b'A'));
+        assert_eq!(apple_key(HostKey::Char('!'), false), Some(b'!'));
+        assert_eq!(apple_key(HostKey::Char('é'), false), None);
+    }
+
+    #[test]
+    fn keyboard_named_keys() {
+        assert_eq!(apple_key(HostKey::Left, false), Some(0x08));
+        assert_eq!(apple_key(HostKey::Right, false), Some(0x15));
+        assert_eq!(apple_key(HostKey::Up, false), Some(0x0B));
+        assert_eq!(apple_key(HostKey::Down, false), Some(0x0A));
+        assert_eq!(apple_key(HostKey::Backspace, false), Some(0x08));
+        assert_eq!(apple_key(HostKey::Delete, false), Some(0x7F));
+        assert_eq!(apple_key(HostKey::Escape, false), Some(0x1B));
+        assert_eq!(apple_key(HostKey::Tab, false), Some(0x09));
+    }
+}
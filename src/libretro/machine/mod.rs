@@ -1,9 +1,23 @@
 mod apple_ii_e;
+pub mod apple_ii_e_mmu;
+pub mod apple_ii_roms;
 pub mod apple_iie_e_display;
-mod apple_ii_e_string;
+pub mod apple_ii_e_string;
+pub mod framebuffer;
+pub mod keyboard;
+pub mod monitor;
+pub mod ntsc;
+pub mod recorder;
+pub mod video_scanner;
+pub mod viewport;
 
 pub use apple_ii_e::AppleIIe;
 
+use crate::machine::apple_ii_e_string::AppleIIeString;
+use crate::machine::apple_iie_e_display::TextScreen;
+use crate::memory::{MemoryManager, WatchHit};
+use std::fmt::{Display, Formatter};
+
 pub trait Machine {
     fn reset(&mut self);
     fn cycle(&mut self);
@@ -11,4 +25,48 @@ pub trait Machine {
 
     fn read(&self, addr: usize) -> u8;
     fn write(&mut self, addr: usize, data: u8);
+
+    fn get_memory(&mut self) -> &mut MemoryManager;
+    /// Returns and clears the watchpoint hits since the last call
+    fn take_watch_hits(&mut self) -> Vec<WatchReport>;
+
+    /// Cycles since power on
+    fn clock(&self) -> usize;
+    /// Returns and clears whether the beam has finished a frame since the last call
+    fn take_frame_done(&mut self) -> bool;
+    /// Redraws the whole frame from video memory, for when the CPU is stopped
+    fn redraw(&mut self);
+    /// Copies the last frame drawn by the beam into an RGBA frame
+    fn render(&self, frame: &mut [u8]);
+    /// Screen codes on the displayed text page
+    fn text_screen(&self) -> TextScreen;
+
+    /// Presses a key with a 7-bit ASCII code, latching it for the CPU
+    fn key_down(&mut self, code: u8);
+    /// Releases every key
+    fn key_up(&mut self);
+    /// Sets whether the Open Apple and Solid Apple keys are held
+    fn set_apple_keys(&mut self, open: bool, solid: bool);
+
+    /// The 24 lines of the displayed text page
+    fn screen_text(&self) -> Vec<String> {
+        let screen = self.text_screen();
+        screen
+            .rows
+            .into_iter()
+            .map(|row| AppleIIeString::from_a2(row, screen.altcharset).string)
+            .collect()
+    }
+}
+
+/// A watchpoint hit along with the instruction that caused it
+pub struct WatchReport {
+    pub hit: WatchHit,
+    pub instruction: String,
+}
+
+impl Display for WatchReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n  at {}", self.hit, self.instruction)
+    }
 }
\ No newline at end of file
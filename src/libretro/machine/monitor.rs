@@ -0,0 +1,193 @@
+/**
+ * Monitor emulation
+ *
+ * Post-processing over the RGBA frame drawn by `Display`: the colour killer
+ * that drops colour while the screen is all text, monochrome phosphors and dark
+ * gaps between scanlines. User palettes are applied by `Display` itself, where
+ * lo-res colours are still known by number: the built-in greys 5 and 10 are the
+ * same RGB, so a pass over the frame could not give them different colours.
+ * The colour killer and monochrome phosphors then work from the palette's RGB.
+ */
+use crate::machine::apple_ii_e_mmu::Switches;
+use crate::machine::apple_iie_e_display::{DISPLAY_WIDTH, lores_rgb};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Phosphor colour of the monitor
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Phosphor {
+    #[default]
+    Color,
+    Green,
+    Amber,
+    White,
+}
+
+impl Phosphor {
+    /// Colour of a fully lit dot, or `None` for a colour monitor
+    fn tint(&self) -> Option<[u8; 3]> {
+        match self {
+            Phosphor::Color => None,
+            Phosphor::Green => Some([51, 255, 51]),
+            Phosphor::Amber => Some([255, 176, 0]),
+            Phosphor::White => Some([255, 255, 255]),
+        }
+    }
+}
+
+impl FromStr for Phosphor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "color" => Ok(Phosphor::Color),
+            "green" => Ok(Phosphor::Green),
+            "amber" => Ok(Phosphor::Amber),
+            "white" => Ok(Phosphor::White),
+            _ => Err(format!(
+                "Invalid phosphor {}, expected color, green, amber or white",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Phosphor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phosphor::Color => write!(f, "color"),
+            Phosphor::Green => write!(f, "green"),
+            Phosphor::Amber => write!(f, "amber"),
+            Phosphor::White => write!(f, "white"),
+        }
+    }
+}
+
+/// The 16 lo-res colours
+///
+/// A palette file has one `RRGGBB` hex colour per line in lo-res order. Blank
+/// lines and anything after `#` are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette(pub [[u8; 3]; 16]);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self(std::array::from_fn(|i| lores_rgb(i as u8)))
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors = s
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let rgb = u32::from_str_radix(line, 16)
+                    .ok()
+                    .filter(|_| line.len() == 6)
+                    .ok_or_else(|| format!("Invalid colour: {}", line))?;
+                Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let count = colors.len();
+        colors
+            .try_into()
+            .map(Palette)
+            .map_err(|_| format!("Expected 16 colours, found {}", count))
+    }
+}
+
+fn luminance([r, g, b]: [u8; 3]) -> f32 {
+    (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Monitor {
+    pub phosphor: Phosphor,
+    /// Darken every other row like the gaps between scanlines
+    pub scanlines: bool,
+}
+
+impl Monitor {
+    /// Post-processes an RGBA frame drawn with display `switches`
+    pub fn apply(&self, frame: &mut [u8], switches: Switches) {
+        let (pixels, _) = frame.as_chunks_mut::<4>();
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let mut rgb = [pixel[0], pixel[1], pixel[2]];
+            if let Some(tint) = self.phosphor.tint() {
+                let y = luminance(rgb);
+                rgb = tint.map(|c| (c as f32 * y).round() as u8);
+            } else if switches.text {
+                rgb = [(luminance(rgb) * 255.0).round() as u8; 3];
+            }
+            if self.scanlines && (i / DISPLAY_WIDTH) % 2 == 1 {
+                rgb = rgb.map(|c| c / 2);
+            }
+            pixel[..3].copy_from_slice(&rgb);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::apple_ii_e_mmu::Switches;
+    use crate::machine::apple_iie_e_display::DISPLAY_WIDTH;
+    use crate::machine::monitor::{Monitor, Palette, Phosphor};
+
+    /// Two rows of the frame filled with one colour
+    fn frame(rgb: [u8; 3]) -> Vec<u8> {
+        [rgb[0], rgb[1], rgb[2], 0xFF].repeat(DISPLAY_WIDTH * 2)
+    }
+
+    fn graphics() -> Switches {
+        Switches {
+            text: false,
+            ..Switches::default()
+        }
+    }
+
+    #[test]
+    fn monitor_phosphor() {
+        let monitor = Monitor {
+            phosphor: Phosphor::Amber,
+            ..Monitor::default()
+        };
+        let mut white = frame([255, 255, 255]);
+        monitor.apply(&mut white, graphics());
+        assert_eq!(white[0..4], [255, 176, 0, 0xFF]);
+
+        // Only text mode kills the colour on a colour monitor
+        let mut orange = frame([255, 106, 60]);
+        Monitor::default().apply(&mut orange, graphics());
+        assert_eq!(orange[0..3], [255, 106, 60]);
+        Monitor::default().apply(&mut orange, Switches::default());
+        assert_eq!(orange[0], orange[1]);
+        assert_eq!(orange[1], orange[2]);
+    }
+
+    #[test]
+    fn monitor_scanlines() {
+        let monitor = Monitor {
+            scanlines: true,
+            ..Monitor::default()
+        };
+        let mut color = frame([0x80, 0x40, 0x20]);
+        monitor.apply(&mut color, graphics());
+        assert_eq!(color[0..3], [0x80, 0x40, 0x20]);
+        let odd = DISPLAY_WIDTH * 4;
+        assert_eq!(color[odd..odd + 3], [0x40, 0x20, 0x10]);
+    }
+
+    #[test]
+    fn palette_parse() {
+        let file = "# Black\n000000\n".to_string() + &"FFFFFF\n".repeat(15);
+        let palette: Palette = file.parse().unwrap();
+        assert_eq!(palette.0[0], [0, 0, 0]);
+        assert_eq!(palette.0[15], [255, 255, 255]);
+        assert!("000000\n".parse::<Palette>().is_err());
+        assert!("00000G\n".repeat(16).parse::<Palette>().is_err());
+    }
+}
@@ -0,0 +1,149 @@
+/**
+ * NTSC composite video decoder
+ *
+ * The Apple II generates colour by feeding its 14.318 MHz dot stream straight
+ * into the composite signal, so there are four dots to every cycle of the
+ * 3.58 MHz colour subcarrier. This decodes a scanline of dots the way a monitor
+ * does: luma is the signal averaged over a short window, and I and Q are the
+ * signal demodulated against the subcarrier over a longer one.
+ */
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// Samples per colour subcarrier cycle
+const SAMPLES_PER_CYCLE: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscFilter {
+    /// Samples averaged for luma, fewer is sharper
+    pub luma_width: usize,
+    /// Samples averaged for chroma, fewer gives more colour fringing
+    ///
+    /// A whole number of subcarrier cycles, so luma does not leak into I and Q.
+    pub chroma_width: usize,
+    pub saturation: f32,
+    /// Subcarrier phase of the first dot, in degrees
+    pub hue: f32,
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        Self {
+            luma_width: 3,
+            chroma_width: 8,
+            saturation: 0.6,
+            hue: 36.0,
+        }
+    }
+}
+
+impl NtscFilter {
+    /// Converts a scanline of composite samples to RGB
+    pub fn decode(&self, signal: &[bool]) -> Vec<[u8; 3]> {
+        let sample = |x: isize| -> f32 {
+            match usize::try_from(x).ok().and_then(|x| signal.get(x)) {
+                Some(true) => 1.0,
+                _ => 0.0,
+            }
+        };
+        // The subcarrier only takes four phases, one per sample of a cycle
+        let hue = self.hue.to_radians();
+        let carrier: [(f32, f32); SAMPLES_PER_CYCLE] = std::array::from_fn(|k| {
+            let phase = 2.0 * PI * k as f32 / SAMPLES_PER_CYCLE as f32 + hue;
+            (phase.cos(), phase.sin())
+        });
+        let window = |x: usize, width: usize| {
+            let start = x as isize - (width / 2) as isize;
+            start..start + width.max(1) as isize
+        };
+
+        (0..signal.len())
+            .map(|x| {
+                let y = window(x, self.luma_width).map(sample).sum::<f32>()
+                    / self.luma_width.max(1) as f32;
+
+                let (mut i, mut q) = (0.0, 0.0);
+                for k in window(x, self.chroma_width) {
+                    let (cos, sin) = carrier[k.rem_euclid(SAMPLES_PER_CYCLE as isize) as usize];
+                    i += sample(k) * cos;
+                    q += sample(k) * sin;
+                }
+                let scale = 2.0 * self.saturation / self.chroma_width.max(1) as f32;
+                let (i, q) = (i * scale, q * scale);
+
+                let rgb = [
+                    y + 0.956 * i + 0.621 * q,
+                    y - 0.272 * i - 0.647 * q,
+                    y - 1.106 * i + 1.703 * q,
+                ];
+                rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            })
+            .collect()
+    }
+}
+
+impl FromStr for NtscFilter {
+    type Err = String;
+
+    /// Parses `<luma width>,<chroma width>`, e.g. `3,8`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid NTSC filter: {}", s);
+        let (luma, chroma) = s.split_once(',').ok_or_else(err)?;
+        let chroma_width: usize = chroma.trim().parse().map_err(|_| err())?;
+        if chroma_width == 0 || !chroma_width.is_multiple_of(SAMPLES_PER_CYCLE) {
+            return Err(format!(
+                "Invalid NTSC filter: {}, chroma width must be a multiple of {}",
+                s, SAMPLES_PER_CYCLE
+            ));
+        }
+        Ok(Self {
+            luma_width: luma.trim().parse().map_err(|_| err())?,
+            chroma_width,
+            ..Self::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::ntsc::NtscFilter;
+
+    /// A line repeating the four bits of lo-res colour `color`
+    fn solid(color: u8) -> Vec<bool> {
+        (0..560).map(|x| (color >> (x % 4)) & 1 != 0).collect()
+    }
+
+    #[test]
+    fn ntsc_solid_colors() {
+        let filter = NtscFilter::default();
+        assert_eq!(filter.decode(&solid(0))[280], [0, 0, 0]);
+        assert_eq!(filter.decode(&solid(15))[280], [255, 255, 255]);
+
+        // Orange is mostly red and green is mostly green
+        let [r, g, b] = filter.decode(&solid(9))[280];
+        assert!(r > g && g > b);
+        let [r, g, b] = filter.decode(&solid(12))[280];
+        assert!(g > r && g > b);
+    }
+
+    #[test]
+    fn ntsc_fringing() {
+        // A white bar picks up colour at its edges but not in the middle
+        let mut line = vec![false; 560];
+        line[100..140].fill(true);
+        let rgb = NtscFilter::default().decode(&line);
+        assert_eq!(rgb[120], [255, 255, 255]);
+        let [r, g, b] = rgb[139];
+        assert!(r != g || g != b);
+    }
+
+    #[test]
+    fn ntsc_parse() {
+        let filter: NtscFilter = "2,12".parse().unwrap();
+        assert_eq!(filter.luma_width, 2);
+        assert_eq!(filter.chroma_width, 12);
+        assert!("2".parse::<NtscFilter>().is_err());
+        assert!("2,6".parse::<NtscFilter>().is_err());
+        assert!("2,0".parse::<NtscFilter>().is_err());
+    }
+}
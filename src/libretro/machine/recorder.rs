@@ -0,0 +1,231 @@
+/**
+ * Video capture
+ *
+ * Records frames from a `Framebuffer` at the native frame rate of about 59.92
+ * frames per second, either as an animated GIF or as a Y4M file of uncompressed
+ * 4:4:4 YCbCr frames for video tools.
+ *
+ * GIFs keep every other frame, at about 29.96 frames per second, because their
+ * delays are whole centiseconds and browsers slow delays under 2cs down to 10cs.
+ */
+use crate::machine::framebuffer::Framebuffer;
+use crate::machine::video_scanner::CYCLES_PER_FRAME;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Average CPU cycles per second, counting the stretched cycle at the end of each line
+const CPU_HZ: usize = 1_020_484;
+
+/// Native frames per GIF frame
+const GIF_STEP: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VideoFormat {
+    Gif,
+    Y4m,
+}
+
+impl VideoFormat {
+    /// Picks the format from a `.gif` or `.y4m` extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "gif" => Some(VideoFormat::Gif),
+            "y4m" => Some(VideoFormat::Y4m),
+            _ => None,
+        }
+    }
+}
+
+enum Encoder<W: Write> {
+    Gif(gif::Encoder<W>),
+    Y4m(W),
+}
+
+pub struct Recorder<W: Write> {
+    encoder: Encoder<W>,
+    width: usize,
+    height: usize,
+    frames: usize,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: &Path, width: usize, height: usize) -> io::Result<Self> {
+        let format = VideoFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "expected a .gif or .y4m file")
+        })?;
+        Self::new(BufWriter::new(File::create(path)?), format, width, height)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut w: W, format: VideoFormat, width: usize, height: usize) -> io::Result<Self> {
+        let encoder = match format {
+            VideoFormat::Gif => {
+                let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &[])
+                    .map_err(io::Error::other)?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(io::Error::other)?;
+                Encoder::Gif(encoder)
+            }
+            VideoFormat::Y4m => {
+                writeln!(
+                    w,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                    width, height, CPU_HZ, CYCLES_PER_FRAME
+                )?;
+                Encoder::Y4m(w)
+            }
+        };
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            frames: 0,
+        })
+    }
+
+    /// Native frames recorded so far, including those a GIF leaves out
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn write_frame(&mut self, frame: &Framebuffer) -> io::Result<()> {
+        if frame.width() != self.width || frame.height() != self.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size changed while recording",
+            ));
+        }
+        match &mut self.encoder {
+            Encoder::Gif(encoder) if self.frames.is_multiple_of(GIF_STEP) => {
+                let mut gif_frame = gif_frame(frame);
+                let delay = gif_time(self.frames + GIF_STEP) - gif_time(self.frames);
+                gif_frame.delay = delay as u16;
+                encoder.write_frame(&gif_frame).map_err(io::Error::other)?;
+            }
+            Encoder::Gif(_) => {}
+            Encoder::Y4m(w) => {
+                w.write_all(b"FRAME\n")?;
+                w.write_all(&ycbcr_planes(frame))?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Finishes the file and returns the writer
+    pub fn finish(self) -> io::Result<W> {
+        match self.encoder {
+            Encoder::Gif(encoder) => encoder.into_inner().map_err(io::Error::other),
+            Encoder::Y4m(mut w) => {
+                w.flush()?;
+                Ok(w)
+            }
+        }
+    }
+}
+
+/// Centiseconds from the start of the recording to frame `n`
+///
+/// GIF delays are whole centiseconds, so rounding the time of each frame keeps
+/// the average rate right with a mix of 3cs and 4cs delays.
+fn gif_time(n: usize) -> usize {
+    (n * 100 * CYCLES_PER_FRAME + CPU_HZ / 2) / CPU_HZ
+}
+
+/// Uses the exact colours when there are few enough, as there are without NTSC
+fn gif_frame(frame: &Framebuffer) -> gif::Frame<'static> {
+    let (width, height) = (frame.width() as u16, frame.height() as u16);
+    let mut palette = Vec::new();
+    let mut colors = HashMap::new();
+    let mut pixels = Vec::with_capacity(frame.width() * frame.height());
+    for rgba in frame.data().chunks_exact(4) {
+        let rgb = [rgba[0], rgba[1], rgba[2]];
+        let next = colors.len();
+        let index = *colors.entry(rgb).or_insert(next);
+        if index == next {
+            if next == 256 {
+                let mut data = frame.data().to_vec();
+                return gif::Frame::from_rgba_speed(width, height, &mut data, 10);
+            }
+            palette.extend_from_slice(&rgb);
+        }
+        pixels.push(index as u8);
+    }
+    gif::Frame::from_palette_pixels(width, height, pixels, palette, None)
+}
+
+/// Y, Cb and Cr planes in the studio range of BT.601
+fn ycbcr_planes(frame: &Framebuffer) -> Vec<u8> {
+    let pixels = frame.width() * frame.height();
+    let mut planes = vec![0; pixels * 3];
+    for (i, rgba) in frame.data().chunks_exact(4).enumerate() {
+        let [r, g, b] = [rgba[0], rgba[1], rgba[2]].map(|x| x as f32 / 255.0);
+        let y = 16.0 + 65.481 * r + 128.553 * g + 24.966 * b;
+        let cb = 128.0 - 37.797 * r - 74.203 * g + 112.0 * b;
+        let cr = 128.0 + 112.0 * r - 93.786 * g - 18.214 * b;
+        planes[i] = y.round() as u8;
+        planes[pixels + i] = cb.round() as u8;
+        planes[pixels * 2 + i] = cr.round() as u8;
+    }
+    planes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::framebuffer::Framebuffer;
+    use crate::machine::recorder::{Recorder, VideoFormat};
+    use std::path::Path;
+
+    #[test]
+    fn recorder_y4m() {
+        let mut frame = Framebuffer::new(2, 1);
+        frame
+            .data_mut()
+            .copy_from_slice(&[0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let mut recorder = Recorder::new(Vec::new(), VideoFormat::Y4m, 2, 1).unwrap();
+        recorder.write_frame(&frame).unwrap();
+        assert_eq!(recorder.frames(), 1);
+
+        let data = recorder.finish().unwrap();
+        let header = b"YUV4MPEG2 W2 H1 F1020484:17030 Ip A1:1 C444\n";
+        assert_eq!(&data[..header.len()], header);
+        assert_eq!(
+            &data[header.len()..],
+            b"FRAME\n\x10\xEB\x80\x80\x80\x80".as_slice()
+        );
+
+        let mut recorder = Recorder::new(Vec::new(), VideoFormat::Y4m, 2, 1).unwrap();
+        assert!(recorder.write_frame(&Framebuffer::new(1, 1)).is_err());
+    }
+
+    #[test]
+    fn recorder_gif() {
+        let frame = Framebuffer::new(4, 4);
+        let mut recorder = Recorder::new(Vec::new(), VideoFormat::Gif, 4, 4).unwrap();
+        for _ in 0..7 {
+            recorder.write_frame(&frame).unwrap();
+        }
+        assert_eq!(recorder.frames(), 7);
+        let data = recorder.finish().unwrap();
+
+        // Every other frame is kept, with delays averaging out at half the native rate
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(data.as_slice())
+            .unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, [3, 4, 3, 3]);
+
+        assert_eq!(
+            VideoFormat::from_path(Path::new("a.GIF")),
+            Some(VideoFormat::Gif)
+        );
+        assert_eq!(VideoFormat::from_path(Path::new("a.avi")), None);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::machine::apple_ii_e_mmu::Switches;
+use crate::memory::{FloatingBus, Memory, MemoryManager};
+use std::cell::Cell;
+use std::rc::Rc;
+
+pub const CYCLES_PER_LINE: usize = 65;
+pub const LINES_PER_FRAME: usize = 262;
+pub const CYCLES_PER_FRAME: usize = CYCLES_PER_LINE * LINES_PER_FRAME;
+
+pub const VISIBLE_LINES: usize = 192;
+pub const VISIBLE_COLUMNS: usize = 40;
+
+/// Vertical counter value of the first line of a frame
+const FIRST_LINE: usize = 0xFA;
+/// Line of a frame where the display starts, at vertical counter $100
+const FIRST_VISIBLE_LINE: usize = 0x100 - FIRST_LINE;
+/// Cycle of a line where the display starts, after horizontal blanking
+const FIRST_VISIBLE_CYCLE: usize = CYCLES_PER_LINE - VISIBLE_COLUMNS;
+
+/// Display soft switch state that affects which memory the video scanner fetches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VideoMode {
+    pub text: bool,
+    pub mixed: bool,
+    pub page2: bool,
+    pub hires: bool,
+}
+
+impl From<Switches> for VideoMode {
+    /// With 80STORE on, PAGE2 banks auxiliary memory instead of flipping pages
+    fn from(s: Switches) -> Self {
+        Self {
+            text: s.text,
+            mixed: s.mixed,
+            page2: s.page2 && !s.store80,
+            hires: s.hires,
+        }
+    }
+}
+
+impl Default for VideoMode {
+    fn default() -> Self {
+        Self {
+            text: true,
+            mixed: false,
+            page2: false,
+            hires: false,
+        }
+    }
+}
+
+/// Address fetched by the video scanner `cycle` cycles into a frame
+///
+/// Follows the counter and adder equations in Sather's Understanding the Apple IIe,
+/// including the addresses fetched during horizontal and vertical blanking.
+pub fn scanner_address(cycle: usize, mode: VideoMode) -> usize {
+    let cycle = cycle % CYCLES_PER_FRAME;
+    let h = match cycle % CYCLES_PER_LINE {
+        0 => 0x00,
+        x => 0x3F + x,
+    };
+    let v = FIRST_LINE + cycle / CYCLES_PER_LINE;
+
+    let v34 = (v >> 6) & 0x3;
+    let sum = (0xD + ((h >> 3) & 0x7) + (v34 << 2 | v34)) & 0xF;
+    let mut addr = (h & 0x7) | (sum << 3) | (((v >> 3) & 0x7) << 7);
+
+    // The bottom four text rows of mixed mode are fetched like text
+    let row = (v >> 3) & 0x1F;
+    let text = mode.text || (mode.mixed && row >= 20);
+    let page = if mode.page2 { 2 } else { 1 };
+    if text || !mode.hires {
+        addr |= page << 10;
+    } else {
+        addr |= (v & 0x7) << 10;
+        addr |= page << 13;
+    }
+    addr
+}
+
+/// Visible scanline and column the beam is on `cycle` cycles into a frame
+pub fn beam_position(cycle: usize) -> Option<(usize, usize)> {
+    let cycle = cycle % CYCLES_PER_FRAME;
+    let line = (cycle / CYCLES_PER_LINE).checked_sub(FIRST_VISIBLE_LINE)?;
+    let col = (cycle % CYCLES_PER_LINE).checked_sub(FIRST_VISIBLE_CYCLE)?;
+    (line < VISIBLE_LINES).then_some((line, col))
+}
+
+/// Whether the beam is in vertical blanking `cycle` cycles into a frame
+pub fn is_vblank(cycle: usize) -> bool {
+    let line = cycle % CYCLES_PER_FRAME / CYCLES_PER_LINE;
+    !(FIRST_VISIBLE_LINE..FIRST_VISIBLE_LINE + VISIBLE_LINES).contains(&line)
+}
+
+/// Drives the floating bus from the byte the video scanner last fetched
+pub struct VideoScanner {
+    clock: Rc<Cell<usize>>,
+    switches: Rc<Cell<Switches>>,
+}
+
+impl VideoScanner {
+    pub fn new(clock: Rc<Cell<usize>>, switches: Rc<Cell<Switches>>) -> Self {
+        Self { clock, switches }
+    }
+
+    pub fn address(&self) -> usize {
+        scanner_address(self.clock.get(), self.switches.get().into())
+    }
+}
+
+impl FloatingBus for VideoScanner {
+    fn read(&self, mem: &MemoryManager) -> u8 {
+        mem.peek(self.address())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::apple_ii_e_mmu::Switches;
+    use crate::machine::video_scanner::{
+        CYCLES_PER_FRAME, CYCLES_PER_LINE, VideoMode, beam_position, is_vblank, scanner_address,
+    };
+
+    /// Cycle of column `col` on visible scanline `line`
+    fn at(line: usize, col: usize) -> usize {
+        (6 + line) * CYCLES_PER_LINE + 25 + col
+    }
+
+    #[test]
+    fn scanner_address_text() {
+        let text = VideoMode::default();
+        assert_eq!(scanner_address(at(0, 0), text), 0x400);
+        assert_eq!(scanner_address(at(7, 0), text), 0x400);
+        assert_eq!(scanner_address(at(8, 0), text), 0x480);
+        assert_eq!(scanner_address(at(64, 39), text), 0x44F);
+        assert_eq!(scanner_address(at(191, 39), text), 0x7F7);
+
+        let page2 = VideoMode {
+            page2: true,
+            ..text
+        };
+        assert_eq!(scanner_address(at(0, 0), page2), 0x800);
+    }
+
+    #[test]
+    fn scanner_address_hires() {
+        let hires = VideoMode {
+            text: false,
+            hires: true,
+            ..VideoMode::default()
+        };
+        assert_eq!(scanner_address(at(0, 0), hires), 0x2000);
+        assert_eq!(scanner_address(at(1, 0), hires), 0x2400);
+        assert_eq!(scanner_address(at(8, 5), hires), 0x2085);
+
+        let page2 = VideoMode {
+            page2: true,
+            ..hires
+        };
+        assert_eq!(scanner_address(at(0, 0), page2), 0x4000);
+
+        let mixed = VideoMode {
+            mixed: true,
+            ..hires
+        };
+        assert_eq!(scanner_address(at(159, 0), mixed), 0x3DD0);
+        assert_eq!(scanner_address(at(160, 0), mixed), 0x650);
+    }
+
+    #[test]
+    fn video_mode_from_switches() {
+        let page2 = Switches {
+            page2: true,
+            ..Switches::default()
+        };
+        assert_eq!(scanner_address(at(0, 0), page2.into()), 0x800);
+
+        let store80 = Switches {
+            store80: true,
+            ..page2
+        };
+        assert_eq!(VideoMode::from(store80), VideoMode::default());
+    }
+
+    #[test]
+    fn beam_vblank() {
+        assert_eq!(beam_position(at(0, 0)), Some((0, 0)));
+        assert_eq!(beam_position(at(191, 39)), Some((191, 39)));
+        assert_eq!(beam_position(at(10, 0) - 1), None);
+        assert_eq!(beam_position(at(192, 0)), None);
+        assert_eq!(beam_position(CYCLES_PER_FRAME + at(5, 3)), Some((5, 3)));
+
+        assert!(is_vblank(0));
+        assert!(!is_vblank(at(0, 0)));
+        assert!(!is_vblank(at(191, 39)));
+        assert!(is_vblank(at(192, 0)));
+        assert!(is_vblank(CYCLES_PER_FRAME - 1));
+    }
+}
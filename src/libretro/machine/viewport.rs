@@ -0,0 +1,226 @@
+/**
+ * Window scaling
+ *
+ * Works out where a frame goes in a window of any size and copies it there with
+ * nearest neighbour sampling, so dots stay sharp. Aspect correction stretches the
+ * 560-dot wide frame to the 4:3 shape of a monitor, which also suits the 280-dot
+ * modes since they are drawn doubled.
+ */
+use crate::machine::apple_iie_e_display::DISPLAY_SCALE;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Scaling {
+    /// Largest whole multiple of the frame size that fits
+    #[default]
+    Integer,
+    /// Fill as much of the window as possible
+    Fit,
+}
+
+impl FromStr for Scaling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "integer" => Ok(Scaling::Integer),
+            "fit" => Ok(Scaling::Fit),
+            _ => Err(format!("Invalid scaling {}, expected integer or fit", s)),
+        }
+    }
+}
+
+impl Display for Scaling {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scaling::Integer => write!(f, "integer"),
+            Scaling::Fit => write!(f, "fit"),
+        }
+    }
+}
+
+/// Window scale settings
+///
+/// Saved as `key = value` lines for `scale`, `scaling` and `aspect`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// Window size as a multiple of the frame size
+    pub scale: u32,
+    pub scaling: Scaling,
+    /// Stretch the frame to 4:3
+    pub aspect: bool,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            scale: DISPLAY_SCALE,
+            scaling: Scaling::default(),
+            aspect: false,
+        }
+    }
+}
+
+impl Viewport {
+    /// Size of a `frame` sized frame before scaling
+    pub fn base_size(&self, frame: (u32, u32)) -> (u32, u32) {
+        if self.aspect {
+            (frame.0, frame.0 * 3 / 4)
+        } else {
+            frame
+        }
+    }
+
+    /// Window size that shows a frame at `scale`
+    pub fn window_size(&self, frame: (u32, u32)) -> (u32, u32) {
+        let (width, height) = self.base_size(frame);
+        (width * self.scale, height * self.scale)
+    }
+
+    /// Largest whole scale of the frame that fits in `window`
+    pub fn fit_scale(&self, window: (u32, u32), frame: (u32, u32)) -> u32 {
+        let (width, height) = self.base_size(frame);
+        (window.0 / width).min(window.1 / height)
+    }
+
+    /// Position and size of the frame in `window` as x, y, width and height
+    pub fn rect(&self, window: (u32, u32), frame: (u32, u32)) -> (u32, u32, u32, u32) {
+        let (base_width, base_height) = self.base_size(frame);
+        let scale = self.fit_scale(window, frame);
+        let (width, height) = if self.scaling == Scaling::Integer && scale > 0 {
+            (base_width * scale, base_height * scale)
+        } else {
+            let scale =
+                (window.0 as f64 / base_width as f64).min(window.1 as f64 / base_height as f64);
+            (
+                (base_width as f64 * scale) as u32,
+                (base_height as f64 * scale) as u32,
+            )
+        };
+        (
+            (window.0 - width) / 2,
+            (window.1 - height) / 2,
+            width,
+            height,
+        )
+    }
+
+    /// Copies an RGBA frame into an RGBA window buffer, with black borders
+    pub fn blit(&self, src: &[u8], frame: (u32, u32), dst: &mut [u8], window: (u32, u32)) {
+        let (x, y, width, height) = self.rect(window, frame);
+        let (src, _) = src.as_chunks::<4>();
+        let (dst, _) = dst.as_chunks_mut::<4>();
+        dst.fill([0, 0, 0, 0xFF]);
+
+        let columns: Vec<usize> = (0..width)
+            .map(|i| (i as usize * frame.0 as usize) / width as usize)
+            .collect();
+        for j in 0..height {
+            let src_row = (j as usize * frame.1 as usize) / height as usize * frame.0 as usize;
+            let dst_row = (y + j) as usize * window.0 as usize + x as usize;
+            for (i, &column) in columns.iter().enumerate() {
+                dst[dst_row + i] = src[src_row + column];
+            }
+        }
+    }
+}
+
+impl FromStr for Viewport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut viewport = Viewport::default();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("Invalid setting: {}", line))?;
+            match key {
+                "scale" => {
+                    viewport.scale = value
+                        .parse()
+                        .ok()
+                        .filter(|&x| x > 0)
+                        .ok_or_else(|| format!("Invalid scale: {}", value))?
+                }
+                "scaling" => viewport.scaling = value.parse()?,
+                "aspect" => {
+                    viewport.aspect = value
+                        .parse()
+                        .map_err(|_| format!("Invalid aspect: {}", value))?
+                }
+                _ => return Err(format!("Unknown setting: {}", key)),
+            }
+        }
+        Ok(viewport)
+    }
+}
+
+impl Display for Viewport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "scale = {}", self.scale)?;
+        writeln!(f, "scaling = {}", self.scaling)?;
+        writeln!(f, "aspect = {}", self.aspect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::viewport::{Scaling, Viewport};
+
+    const FRAME: (u32, u32) = (560, 384);
+
+    #[test]
+    fn viewport_rect() {
+        let integer = Viewport::default();
+        assert_eq!(integer.window_size(FRAME), (1120, 768));
+        assert_eq!(integer.rect((1120, 768), FRAME), (0, 0, 1120, 768));
+        assert_eq!(integer.rect((1300, 800), FRAME), (90, 16, 1120, 768));
+
+        let fit = Viewport {
+            scaling: Scaling::Fit,
+            ..integer
+        };
+        assert_eq!(fit.rect((1300, 768), FRAME), (90, 0, 1120, 768));
+        assert_eq!(fit.rect((840, 1000), FRAME), (0, 212, 840, 576));
+
+        // 4:3 makes the frame 420 lines high
+        let aspect = Viewport {
+            aspect: true,
+            ..integer
+        };
+        assert_eq!(aspect.window_size(FRAME), (1120, 840));
+        assert_eq!(aspect.rect((1920, 1080), FRAME), (400, 120, 1120, 840));
+
+        // Too small for a whole scale falls back to fitting
+        assert_eq!(integer.rect((280, 192), FRAME), (0, 0, 280, 192));
+    }
+
+    #[test]
+    fn viewport_blit() {
+        let src = [[1, 1, 1, 0xFF], [2, 2, 2, 0xFF]].concat();
+        let mut dst = vec![0xAA; 6 * 2 * 4];
+        Viewport::default().blit(&src, (2, 1), &mut dst, (6, 2));
+        let (dst, _) = dst.as_chunks::<4>();
+        let red: Vec<u8> = dst.iter().map(|x| x[0]).collect();
+        assert_eq!(red, [0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0]);
+        assert_eq!(dst[0], [0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn viewport_settings() {
+        let viewport = Viewport {
+            scale: 3,
+            scaling: Scaling::Fit,
+            aspect: true,
+        };
+        assert_eq!(viewport.to_string().parse(), Ok(viewport));
+        assert_eq!("# empty\n".parse(), Ok(Viewport::default()));
+        assert!("scale = 0".parse::<Viewport>().is_err());
+        assert!("zoom = 2".parse::<Viewport>().is_err());
+    }
+}
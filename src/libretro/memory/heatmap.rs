@@ -0,0 +1,164 @@
+/**
+ * Memory access heatmap
+ *
+ * Counts reads, writes and opcode fetches for every address. Counts are exported
+ * as a CSV of the addresses that were touched, or as a PNG with one pixel per
+ * address and one row per page, so a 64K address space is 256x256.
+ */
+use crate::memory::PAGE_SIZE;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Exec,
+}
+
+pub struct Heatmap {
+    reads: Vec<Cell<u32>>,
+    writes: Vec<Cell<u32>>,
+    execs: Vec<Cell<u32>>,
+}
+
+impl Heatmap {
+    pub fn new(size: usize) -> Self {
+        Self {
+            reads: vec![Cell::new(0); size],
+            writes: vec![Cell::new(0); size],
+            execs: vec![Cell::new(0); size],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.reads.len()
+    }
+
+    fn counts(&self, access: Access) -> &[Cell<u32>] {
+        match access {
+            Access::Read => &self.reads,
+            Access::Write => &self.writes,
+            Access::Exec => &self.execs,
+        }
+    }
+
+    pub fn record(&self, access: Access, addr: usize) {
+        if let Some(count) = self.counts(access).get(addr) {
+            count.set(count.get().saturating_add(1));
+        }
+    }
+
+    pub fn count(&self, access: Access, addr: usize) -> u32 {
+        self.counts(access).get(addr).map_or(0, |x| x.get())
+    }
+
+    /// Writes `addr,reads,writes,execs` for every address that was accessed
+    pub fn write_csv(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "addr,reads,writes,execs")?;
+        for addr in 0..self.size() {
+            let reads = self.count(Access::Read, addr);
+            let writes = self.count(Access::Write, addr);
+            let execs = self.count(Access::Exec, addr);
+            if reads == 0 && writes == 0 && execs == 0 {
+                continue;
+            }
+            writeln!(w, "{:04X},{},{},{}", addr, reads, writes, execs)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an RGB image with writes in red, reads in green and execution in blue
+    ///
+    /// Each channel is log scaled against the busiest address of that kind.
+    pub fn write_png(&self, w: impl Write) -> Result<(), png::EncodingError> {
+        let height = self.size().div_ceil(PAGE_SIZE).max(1);
+        let mut data = vec![0u8; PAGE_SIZE * height * 3];
+        for (channel, access) in [Access::Write, Access::Read, Access::Exec]
+            .into_iter()
+            .enumerate()
+        {
+            let max = self
+                .counts(access)
+                .iter()
+                .map(|x| x.get())
+                .max()
+                .unwrap_or(0);
+            if max == 0 {
+                continue;
+            }
+            let scale = 255.0 / f64::from(max).ln_1p();
+            for (addr, count) in self.counts(access).iter().enumerate() {
+                data[addr * 3 + channel] = (f64::from(count.get()).ln_1p() * scale) as u8;
+            }
+        }
+
+        let mut encoder = png::Encoder::new(w, PAGE_SIZE as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()
+    }
+
+    /// Saves `<path>.png` and `<path>.csv`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let png = BufWriter::new(File::create(path.with_extension("png"))?);
+        self.write_png(png).map_err(io::Error::other)?;
+        let mut csv = BufWriter::new(File::create(path.with_extension("csv"))?);
+        self.write_csv(&mut csv)?;
+        csv.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::heatmap::Access;
+    use crate::memory::{Endian, Memory, MemoryManager, RAM};
+
+    #[test]
+    fn heatmap_counts() {
+        let mut ram = RAM::new(0x100, Endian::Little);
+        ram.start_heatmap();
+        ram.write(0x10, 0xEA);
+        ram.read(0x10);
+        ram.read_opcode(0x10);
+
+        let heatmap = ram.stop_heatmap().unwrap();
+        assert_eq!(heatmap.count(Access::Read, 0x10), 1);
+        assert_eq!(heatmap.count(Access::Write, 0x10), 1);
+        assert_eq!(heatmap.count(Access::Exec, 0x10), 1);
+        assert_eq!(heatmap.count(Access::Read, 0x11), 0);
+        assert!(ram.stop_heatmap().is_none());
+
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "addr,reads,writes,execs\n0010,1,1,1\n"
+        );
+    }
+
+    #[test]
+    fn heatmap_memory_manager() {
+        let mut mm = MemoryManager::new(0xFFFF);
+        mm.map(0, "Main RAM", Box::new(RAM::new(0x10000, Endian::Little)));
+        mm.start_heatmap();
+        mm.write(0x300, 0xA9);
+        mm.read_opcode(0x300);
+        mm.read(0xC000);
+
+        let heatmap = mm.stop_heatmap().unwrap();
+        assert_eq!(heatmap.size(), 0x10000);
+        assert_eq!(heatmap.count(Access::Write, 0x300), 1);
+        assert_eq!(heatmap.count(Access::Exec, 0x300), 1);
+        assert_eq!(heatmap.count(Access::Read, 0x300), 0);
+        assert_eq!(heatmap.count(Access::Read, 0xC000), 1);
+
+        let mut png = Vec::new();
+        heatmap.write_png(&mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
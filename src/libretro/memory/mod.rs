@@ -1,11 +1,16 @@
+pub mod heatmap;
 mod ram;
 mod rom;
-mod vram;
+pub mod tools;
+mod watchpoint;
 
-pub use ram::RAM;
-pub use vram::VRAM;
-pub use rom::ROM;
-use std::fmt::Debug;
+pub use ram::{RAM, RamInit};
+pub use rom::{ROM, RomError};
+pub use watchpoint::{WatchCondition, WatchHit, WatchKind, Watchpoint};
+use heatmap::{Access, Heatmap};
+use log::trace;
+use std::cell::RefCell;
+use std::fmt::{Debug, Display};
 
 pub enum Endian {
     Little,
@@ -15,7 +20,20 @@ pub enum Endian {
 pub trait Memory {
     fn write(&mut self, addr: usize, data: u8);
     fn read(&self, addr: usize) -> u8;
+    /// Reads without side effects such as flipping soft switches or counting the access
+    fn peek(&self, addr: usize) -> u8 {
+        self.read(addr)
+    }
+    /// Reads the opcode of an instruction about to be executed
+    fn read_opcode(&self, addr: usize) -> u8 {
+        self.read(addr)
+    }
+    /// Whether the region answers for `addr`, which may leave holes for other regions
     fn is_valid(&self, addr: usize) -> bool;
+    /// Whether reads of `addr` leave the data bus floating, so the floating bus supplies the byte
+    fn is_floating(&self, _addr: usize) -> bool {
+        false
+    }
     fn read_word_zero(&self, addr: u8) -> u16;
     fn read_word(&self, addr: usize) -> u16;
     fn write_word_zero(&mut self, addr: u8, word: u16);
@@ -24,22 +42,79 @@ pub trait Memory {
     fn get_raw(&self) -> &[u8];
 }
 
+/// Supplies the value read from addresses that no region is mapped at
+pub trait FloatingBus {
+    fn read(&self, mem: &MemoryManager) -> u8;
+}
+
 pub type MemoryID = u32;
 
+pub const PAGE_SIZE: usize = 0x100;
+
+/// Which accesses a mapped region responds to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapMode {
+    ReadWrite,
+    ReadOnly,
+    WriteOnly,
+}
+
+impl MapMode {
+    fn is_readable(&self) -> bool {
+        *self != MapMode::WriteOnly
+    }
+
+    fn is_writable(&self) -> bool {
+        *self != MapMode::ReadOnly
+    }
+}
+
 pub struct MMEntry {
     base: usize,
     id: MemoryID,
+    name: String,
+    mode: MapMode,
     region: Box<dyn Memory>,
 }
 
 impl MMEntry {
-    pub fn new(base: usize, region: Box<dyn Memory>) -> Self {
+    pub fn new(
+        base: usize,
+        id: MemoryID,
+        name: &str,
+        mode: MapMode,
+        region: Box<dyn Memory>,
+    ) -> Self {
         Self {
             base,
-            id: 0,
+            id,
+            name: String::from(name),
+            mode,
             region,
         }
     }
+
+    pub fn get_id(&self) -> MemoryID {
+        self.id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_base(&self) -> usize {
+        self.base
+    }
+
+    pub fn region(&self) -> &dyn Memory {
+        self.region.as_ref()
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        self.base <= addr
+            && addr < (self.base + self.region.size())
+            && self.region.is_valid(addr - self.base)
+    }
 }
 
 impl Debug for MMEntry {
@@ -48,14 +123,48 @@ impl Debug for MMEntry {
             .field("base", &format_args!("{:#X}", &self.base))
             .field("len", &format_args!("{:#X}", &self.region.size()))
             .field("id", &self.id)
+            .field("name", &self.name)
+            .field("mode", &self.mode)
             .finish()
     }
 }
 
+/// The regions that reads and writes to a range of pages go to
+#[derive(Debug, PartialEq)]
+pub struct PageMapping {
+    pub start: usize,
+    pub end: usize,
+    pub read: Option<(MemoryID, String)>,
+    pub write: Option<(MemoryID, String)>,
+}
+
+impl Display for PageMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |x: &Option<(MemoryID, String)>| match x {
+            Some((id, name)) => format!("#{} {}", id, name),
+            None => String::from("-"),
+        };
+        write!(
+            f,
+            "${:04X}-${:04X}  R: {:<32} W: {}",
+            self.start,
+            self.end,
+            describe(&self.read),
+            describe(&self.write)
+        )
+    }
+}
+
 pub struct MemoryManager {
     regions: Vec<MMEntry>,
     endian: Endian,
     max_addr: usize,
+    next_region_id: MemoryID,
+    floating_bus: Option<Box<dyn FloatingBus>>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_watch_id: usize,
+    watch_hits: RefCell<Vec<WatchHit>>,
+    heatmap: Option<Heatmap>,
 }
 
 impl MemoryManager {
@@ -64,27 +173,209 @@ impl MemoryManager {
             regions: Vec::new(),
             endian: Endian::Little,
             max_addr,
+            next_region_id: 1,
+            floating_bus: None,
+            watchpoints: Vec::new(),
+            next_watch_id: 1,
+            watch_hits: RefCell::new(Vec::new()),
+            heatmap: None,
         }
     }
 
     pub fn find_by_addr_mut(&mut self, addr: usize) -> Option<&mut MMEntry> {
+        self.regions.iter_mut().find(|x| x.contains(addr))
+    }
+
+    pub fn find_by_addr(&self, addr: usize) -> Option<&MMEntry> {
+        self.regions.iter().find(|x| x.contains(addr))
+    }
+
+    /// Finds the region that reads from `addr` go to
+    pub fn find_readable(&self, addr: usize) -> Option<&MMEntry> {
+        self.regions
+            .iter()
+            .find(|x| x.mode.is_readable() && x.contains(addr))
+    }
+
+    /// Finds the region that writes to `addr` go to
+    pub fn find_writable_mut(&mut self, addr: usize) -> Option<&mut MMEntry> {
         self.regions
             .iter_mut()
-            .find(|x| x.base <= addr && addr < (x.base + x.region.size()))
+            .find(|x| x.mode.is_writable() && x.contains(addr))
     }
 
-    pub fn find_by_addr(&self, addr: usize) -> Option<&MMEntry> {
+    fn find_writable(&self, addr: usize) -> Option<&MMEntry> {
         self.regions
             .iter()
-            .find(|x| x.base <= addr && addr < (x.base + x.region.size()))
+            .find(|x| x.mode.is_writable() && x.contains(addr))
     }
 
     pub fn find_by_id(&mut self, id: MemoryID) -> Option<&mut MMEntry> {
         self.regions.iter_mut().find(|x| x.id == id)
     }
 
-    pub fn map(&mut self, addr: usize, region: Box<dyn Memory>) {
-        self.regions.insert(0, MMEntry::new(addr, region));
+    /// Backing memory of a mapped region, regardless of what is mapped above it
+    pub fn region(&self, id: MemoryID) -> Option<&dyn Memory> {
+        self.regions.iter().find(|x| x.id == id).map(|x| x.region())
+    }
+
+    /// Maps a region at `addr` above all existing regions and returns its ID
+    pub fn map(&mut self, addr: usize, name: &str, region: Box<dyn Memory>) -> MemoryID {
+        self.map_with_mode(addr, name, MapMode::ReadWrite, region)
+    }
+
+    pub fn map_with_mode(
+        &mut self,
+        addr: usize,
+        name: &str,
+        mode: MapMode,
+        region: Box<dyn Memory>,
+    ) -> MemoryID {
+        let id = self.next_region_id;
+        self.next_region_id += 1;
+        self.regions
+            .insert(0, MMEntry::new(addr, id, name, mode, region));
+        id
+    }
+
+    /// Removes a region from the map, handing it back so it can be mapped again later
+    pub fn unmap(&mut self, id: MemoryID) -> Option<Box<dyn Memory>> {
+        let idx = self.regions.iter().position(|x| x.id == id)?;
+        Some(self.regions.remove(idx).region)
+    }
+
+    /// Moves a region to `addr` and raises it above all other regions
+    pub fn remap(&mut self, id: MemoryID, addr: usize) -> bool {
+        let Some(idx) = self.regions.iter().position(|x| x.id == id) else {
+            return false;
+        };
+        let mut entry = self.regions.remove(idx);
+        entry.base = addr;
+        self.regions.insert(0, entry);
+        true
+    }
+
+    /// Lists the effective read and write mapping of every page, merging runs of
+    /// consecutive pages that map to the same regions
+    pub fn page_map(&self) -> Vec<PageMapping> {
+        let describe = |entry: Option<&MMEntry>| entry.map(|x| (x.id, x.name.clone()));
+
+        let mut pages: Vec<PageMapping> = Vec::new();
+        for start in (0..=self.max_addr).step_by(PAGE_SIZE) {
+            let read = describe(self.find_readable(start));
+            let write = describe(self.find_writable(start));
+            match pages.last_mut() {
+                Some(last) if last.read == read && last.write == write => {
+                    last.end = start + PAGE_SIZE - 1;
+                }
+                _ => pages.push(PageMapping {
+                    start,
+                    end: start + PAGE_SIZE - 1,
+                    read,
+                    write,
+                }),
+            }
+        }
+        pages
+    }
+
+    /// Sets what reads from unmapped addresses return instead of 0
+    pub fn set_floating_bus(&mut self, bus: Box<dyn FloatingBus>) {
+        self.floating_bus = Some(bus);
+    }
+
+    /// Adds a watchpoint and returns its ID
+    pub fn add_watchpoint(&mut self, wp: Watchpoint) -> usize {
+        let id = self.next_watch_id;
+        self.next_watch_id += 1;
+        self.watchpoints.push((id, wp));
+        id
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> Option<Watchpoint> {
+        let idx = self.watchpoints.iter().position(|(x, _)| *x == id)?;
+        Some(self.watchpoints.remove(idx).1)
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    /// Returns and clears the watchpoint hits since the last call
+    pub fn take_watch_hits(&self) -> Vec<WatchHit> {
+        self.watch_hits.take()
+    }
+
+    /// Starts counting accesses, discarding any previous counts
+    pub fn start_heatmap(&mut self) {
+        self.heatmap = Some(Heatmap::new(self.max_addr + 1));
+    }
+
+    /// Stops counting accesses and returns the counts
+    pub fn stop_heatmap(&mut self) -> Option<Heatmap> {
+        self.heatmap.take()
+    }
+
+    fn read_access(&self, addr: usize, access: Access) -> u8 {
+        if let Some(heatmap) = &self.heatmap {
+            heatmap.record(access, addr);
+        }
+        let data = match self.find_readable(addr) {
+            None => self.read_floating_bus(),
+            Some(entry) => {
+                let offset = addr - entry.base;
+                let data = match access {
+                    Access::Exec => entry.region.read_opcode(offset),
+                    _ => entry.region.read(offset),
+                };
+                if entry.region.is_floating(offset) {
+                    self.read_floating_bus()
+                } else {
+                    data
+                }
+            }
+        };
+        if !self.watchpoints.is_empty() {
+            self.check_read_watch(addr, data);
+        }
+        data
+    }
+
+    fn read_floating_bus(&self) -> u8 {
+        match &self.floating_bus {
+            None => 0,
+            Some(bus) => bus.read(self),
+        }
+    }
+
+    fn check_read_watch(&self, addr: usize, data: u8) {
+        for (id, wp) in &self.watchpoints {
+            if wp.matches(WatchKind::Read, addr, data) {
+                self.watch_hits.borrow_mut().push(WatchHit {
+                    id: *id,
+                    kind: WatchKind::Read,
+                    addr,
+                    old: data,
+                    new: data,
+                });
+            }
+        }
+    }
+
+    fn check_write_watch(&self, addr: usize, data: u8) {
+        let mut old = None;
+        for (id, wp) in &self.watchpoints {
+            if wp.matches(WatchKind::Write, addr, data) {
+                let old = *old.get_or_insert_with(|| self.peek(addr));
+                self.watch_hits.borrow_mut().push(WatchHit {
+                    id: *id,
+                    kind: WatchKind::Write,
+                    addr,
+                    old,
+                    new: data,
+                });
+            }
+        }
     }
 }
 
@@ -98,8 +389,14 @@ impl Debug for MemoryManager {
 
 impl Memory for MemoryManager {
     fn write(&mut self, addr: usize, data: u8) {
-        match self.find_by_addr_mut(addr) {
-            None => println!("Writing to unmapped memory {:#x}", addr),
+        if let Some(heatmap) = &self.heatmap {
+            heatmap.record(Access::Write, addr);
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_write_watch(addr, data);
+        }
+        match self.find_writable_mut(addr) {
+            None => trace!("Writing to unmapped memory {:#x}", addr),
             Some(entry) => {
                 let offset = addr - entry.base;
                 entry.region.write(offset, data)
@@ -108,18 +405,21 @@ impl Memory for MemoryManager {
     }
 
     fn read(&self, addr: usize) -> u8 {
-        match self.find_by_addr(addr) {
-            None => {
-                println!("Reading from unmapped memory {:#x}", addr);
-                return 0;
-            }
-            Some(entry) => {
-                let offset = addr - entry.base;
-                entry.region.read(offset)
-            }
+        self.read_access(addr, Access::Read)
+    }
+
+    /// Reads without triggering watchpoints or the floating bus
+    fn peek(&self, addr: usize) -> u8 {
+        match self.find_readable(addr) {
+            None => 0,
+            Some(entry) => entry.region.peek(addr - entry.base),
         }
     }
 
+    fn read_opcode(&self, addr: usize) -> u8 {
+        self.read_access(addr, Access::Exec)
+    }
+
     fn is_valid(&self, addr: usize) -> bool {
         addr >= self.max_addr
     }
@@ -180,3 +480,79 @@ impl Memory for MemoryManager {
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::{Endian, MapMode, Memory, MemoryManager, PageMapping, RAM};
+
+    #[test]
+    fn memory_manager_unmap_remap() {
+        let mut mm = MemoryManager::new(0xFFFF);
+        let main = mm.map(0, "Main RAM", Box::new(RAM::new(0x10000, Endian::Little)));
+        let aux = mm.map(0x400, "Aux RAM", Box::new(RAM::new(0x400, Endian::Little)));
+        assert_ne!(main, aux);
+
+        mm.write(0x400, 0xAA);
+        assert_eq!(mm.find_by_addr(0x400).unwrap().get_name(), "Aux RAM");
+        assert_eq!(mm.region(aux).unwrap().get_raw()[0], 0xAA);
+        assert_eq!(mm.region(main).unwrap().get_raw()[0x400], 0x00);
+
+        let region = mm.unmap(aux).unwrap();
+        assert_eq!(region.read(0), 0xAA);
+        assert_eq!(mm.read(0x400), 0x00);
+        assert!(mm.unmap(aux).is_none());
+
+        assert!(mm.remap(main, 0x1000));
+        assert_eq!(mm.find_by_addr(0x1000).unwrap().get_id(), main);
+        assert!(mm.find_by_addr(0x0FFF).is_none());
+        assert!(!mm.remap(aux, 0));
+    }
+
+    #[test]
+    fn memory_manager_page_map() {
+        let mut mm = MemoryManager::new(0xFFFF);
+        let ram = mm.map(0, "Main RAM", Box::new(RAM::new(0xC000, Endian::Little)));
+        let rom = mm.map_with_mode(
+            0xD000,
+            "ROM",
+            MapMode::ReadOnly,
+            Box::new(RAM::new(0x3000, Endian::Little)),
+        );
+        let lc = mm.map_with_mode(
+            0xD000,
+            "Language Card",
+            MapMode::WriteOnly,
+            Box::new(RAM::new(0x3000, Endian::Little)),
+        );
+
+        mm.write(0xD000, 0x12);
+        assert_eq!(mm.read(0xD000), 0x00);
+
+        let main = Some((ram, String::from("Main RAM")));
+        let rom = Some((rom, String::from("ROM")));
+        let lc = Some((lc, String::from("Language Card")));
+        assert_eq!(
+            mm.page_map(),
+            vec![
+                PageMapping {
+                    start: 0,
+                    end: 0xBFFF,
+                    read: main.clone(),
+                    write: main
+                },
+                PageMapping {
+                    start: 0xC000,
+                    end: 0xCFFF,
+                    read: None,
+                    write: None
+                },
+                PageMapping {
+                    start: 0xD000,
+                    end: 0xFFFF,
+                    read: rom,
+                    write: lc
+                },
+            ]
+        );
+    }
+}
@@ -1,21 +1,112 @@
+use crate::memory::heatmap::{Access, Heatmap};
+use crate::memory::tools::parse_byte;
 use crate::memory::{Endian, Memory};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 pub struct RAM {
     memory: Vec<u8>,
     endian: Endian,
+    heatmap: Option<Heatmap>,
+}
+
+/// Contents of RAM at power on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RamInit {
+    Zero,
+    /// Two bytes of $FF then two of $00, repeating, as a real //e powers up
+    AppleIIe,
+    Fixed(u8),
+    Random(u64),
+}
+
+impl RamInit {
+    pub fn fill(&self, memory: &mut [u8]) {
+        match *self {
+            RamInit::Zero => memory.fill(0),
+            RamInit::Fixed(x) => memory.fill(x),
+            RamInit::AppleIIe => {
+                for (addr, byte) in memory.iter_mut().enumerate() {
+                    *byte = if addr & 2 == 0 { 0xFF } else { 0x00 };
+                }
+            }
+            RamInit::Random(seed) => {
+                // xorshift64, which must not be seeded with 0
+                let mut state = seed.max(1);
+                for byte in memory.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = (state >> 32) as u8;
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for RamInit {
+    type Err = String;
+
+    /// Parses `zero`, `apple2e`, `fixed:<byte>` or `random:<seed>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "zero" => Ok(RamInit::Zero),
+            None if s == "apple2e" => Ok(RamInit::AppleIIe),
+            Some(("fixed", x)) => Ok(RamInit::Fixed(parse_byte(x)?)),
+            Some(("random", x)) => x
+                .parse()
+                .map(RamInit::Random)
+                .map_err(|_| format!("Invalid seed: {}", x)),
+            _ => Err(format!(
+                "Invalid RAM pattern {}, expected zero, apple2e, fixed:<byte> or random:<seed>",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for RamInit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RamInit::Zero => write!(f, "zero"),
+            RamInit::AppleIIe => write!(f, "apple2e"),
+            RamInit::Fixed(x) => write!(f, "fixed:{:02X}", x),
+            RamInit::Random(seed) => write!(f, "random:{}", seed),
+        }
+    }
 }
 
 impl RAM {
     pub fn new(size: usize, endian: Endian) -> Self {
+        Self::with_init(size, endian, RamInit::Zero)
+    }
+
+    pub fn with_init(size: usize, endian: Endian, init: RamInit) -> Self {
+        let mut memory = vec![0; size];
+        init.fill(&mut memory);
         RAM {
-            memory: vec![0; size],
+            memory,
             endian,
+            heatmap: None,
         }
     }
+
+    /// Starts counting accesses, discarding any previous counts
+    pub fn start_heatmap(&mut self) {
+        self.heatmap = Some(Heatmap::new(self.memory.len()));
+    }
+
+    /// Stops counting accesses and returns the counts
+    pub fn stop_heatmap(&mut self) -> Option<Heatmap> {
+        self.heatmap.take()
+    }
 }
 impl Memory for RAM {
     fn write(&mut self, addr: usize, data: u8) {
         if self.is_valid(addr) {
+            if let Some(heatmap) = &self.heatmap {
+                heatmap.record(Access::Write, addr);
+            }
             self.memory[addr] = data;
         } else {
             panic!("Address out of bounds: {:?}", addr);
@@ -24,6 +115,28 @@ impl Memory for RAM {
 
     fn read(&self, addr: usize) -> u8 {
         if self.is_valid(addr) {
+            if let Some(heatmap) = &self.heatmap {
+                heatmap.record(Access::Read, addr);
+            }
+            self.memory[addr]
+        } else {
+            panic!("Address out of bounds: {:?}", addr);
+        }
+    }
+
+    fn peek(&self, addr: usize) -> u8 {
+        if self.is_valid(addr) {
+            self.memory[addr]
+        } else {
+            panic!("Address out of bounds: {:?}", addr);
+        }
+    }
+
+    fn read_opcode(&self, addr: usize) -> u8 {
+        if self.is_valid(addr) {
+            if let Some(heatmap) = &self.heatmap {
+                heatmap.record(Access::Exec, addr);
+            }
             self.memory[addr]
         } else {
             panic!("Address out of bounds: {:?}", addr);
@@ -101,4 +214,37 @@ impl RAM {
     pub fn get_raw_mut(&mut self) -> &mut [u8] {
         self.memory.as_mut_slice()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::{Endian, Memory, RAM, RamInit};
+
+    #[test]
+    fn ram_init_patterns() {
+        let ram = RAM::with_init(8, Endian::Little, RamInit::AppleIIe);
+        assert_eq!(
+            ram.get_raw(),
+            &[0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00]
+        );
+
+        let ram = RAM::with_init(4, Endian::Little, RamInit::Fixed(0xA5));
+        assert_eq!(ram.get_raw(), &[0xA5; 4]);
+
+        let a = RAM::with_init(0x100, Endian::Little, RamInit::Random(1234));
+        let b = RAM::with_init(0x100, Endian::Little, RamInit::Random(1234));
+        let c = RAM::with_init(0x100, Endian::Little, RamInit::Random(4321));
+        assert_eq!(a.get_raw(), b.get_raw());
+        assert_ne!(a.get_raw(), c.get_raw());
+    }
+
+    #[test]
+    fn ram_init_parse() {
+        assert_eq!("zero".parse(), Ok(RamInit::Zero));
+        assert_eq!("apple2e".parse(), Ok(RamInit::AppleIIe));
+        assert_eq!("fixed:$EA".parse(), Ok(RamInit::Fixed(0xEA)));
+        assert_eq!("random:42".parse(), Ok(RamInit::Random(42)));
+        assert!("random".parse::<RamInit>().is_err());
+        assert!("fixed:XYZ".parse::<RamInit>().is_err());
+    }
+}
@@ -1,39 +1,123 @@
 use crate::memory::{Endian, Memory};
 use log::{debug, warn};
 use memmap2::Mmap;
-use std::env;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
 pub struct ROM {
     memory: Vec<u8>,
     endian: Endian,
 }
 
+#[derive(Debug)]
+pub enum RomError {
+    /// No search path contained the ROM
+    NotFound { name: String, tried: Vec<PathBuf> },
+    /// The ROM exists but could not be read
+    Io { path: PathBuf, source: io::Error },
+    /// The ROM is bigger than the space it is mapped into
+    TooLarge {
+        path: PathBuf,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::NotFound { name, tried } => {
+                write!(f, "Cannot find ROM {}, tried:", name)?;
+                for path in tried {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
+            }
+            RomError::Io { path, source } => {
+                write!(f, "Cannot read ROM {}: {}", path.display(), source)
+            }
+            RomError::TooLarge {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "ROM {} is {:#X} bytes, expected at most {:#X}",
+                path.display(),
+                actual,
+                expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
 impl ROM {
-    fn get_rom_path(name: &str) -> PathBuf {
-        // Try to get RETRO_PATH from environment, default to "~/.retro" if not set
-        let retro_path =
-            env::var("RETRO_PATH").unwrap_or_else(|_| env::var("HOME").unwrap() + "/.retro");
-        let mut path = PathBuf::from(retro_path);
-        path.push("rom");
-        path.push(name);
-        path
-    }
-
-    pub fn new(_size: usize, endian: Endian, path: &str) -> Self {
-        let rom_path = Self::get_rom_path(path);
-        debug!("Loading ROM from {}", rom_path.display());
+    /// Loads `name` from the first of `search_paths` that has it
+    pub fn new(
+        size: usize,
+        endian: Endian,
+        name: &str,
+        search_paths: &[PathBuf],
+    ) -> Result<Self, RomError> {
+        let mut tried = Vec::new();
+        for dir in search_paths {
+            let rom_path = dir.join(name);
+            if rom_path.is_file() {
+                return Self::load(size, endian, &rom_path);
+            }
+            tried.push(rom_path);
+        }
+        Err(RomError::NotFound {
+            name: String::from(name),
+            tried,
+        })
+    }
 
-        let Ok(file) = File::open(&rom_path) else {
-            panic!("Cannot find file {}", rom_path.display());
+    /// Loads a ROM image of at most `size` bytes, padding short images with $FF
+    pub fn load(size: usize, endian: Endian, rom_path: &Path) -> Result<Self, RomError> {
+        debug!("Loading ROM from {}", rom_path.display());
+        let io_error = |source| RomError::Io {
+            path: rom_path.to_path_buf(),
+            source,
         };
 
-        let mmap = unsafe { Mmap::map(&file).unwrap() };
-        ROM {
-            memory: mmap.to_vec(),
-            endian,
+        let file = File::open(rom_path).map_err(io_error)?;
+        let mmap = unsafe { Mmap::map(&file).map_err(io_error)? };
+        let mut memory = mmap.to_vec();
+
+        if memory.len() > size {
+            return Err(RomError::TooLarge {
+                path: rom_path.to_path_buf(),
+                expected: size,
+                actual: memory.len(),
+            });
+        }
+        if memory.len() < size {
+            warn!(
+                "ROM {} is {:#X} bytes, padding to {:#X}",
+                rom_path.display(),
+                memory.len(),
+                size
+            );
+            memory.resize(size, 0xFF);
         }
+
+        Ok(Self::from_bytes(endian, memory))
+    }
+
+    pub fn from_bytes(endian: Endian, memory: Vec<u8>) -> Self {
+        ROM { memory, endian }
     }
 }
 impl Memory for ROM {
@@ -88,3 +172,54 @@ impl Memory for ROM {
         self.memory.as_slice()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::{Endian, Memory, ROM, RomError};
+    use std::path::PathBuf;
+
+    /// Empty directory for test ROMs, unique to the test
+    fn rom_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rom-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rom_not_found() {
+        let dirs = [rom_dir("missing-a"), rom_dir("missing-b")];
+        match ROM::new(0x100, Endian::Little, "missing.bin", &dirs) {
+            Err(RomError::NotFound { name, tried }) => {
+                assert_eq!(name, "missing.bin");
+                assert_eq!(tried, dirs.map(|dir| dir.join("missing.bin")));
+            }
+            _ => panic!("expected NotFound"),
+        }
+    }
+
+    #[test]
+    fn rom_padding() {
+        let dirs = [rom_dir("padding-a"), rom_dir("padding-b")];
+        std::fs::write(dirs[1].join("short.bin"), [1, 2, 3]).unwrap();
+        let rom = ROM::new(0x10, Endian::Little, "short.bin", &dirs).unwrap();
+        assert_eq!(rom.size(), 0x10);
+        assert_eq!(rom.get_raw()[..4], [1, 2, 3, 0xFF]);
+        assert_eq!(rom.read(0x0F), 0xFF);
+    }
+
+    #[test]
+    fn rom_too_large() {
+        let dir = rom_dir("large");
+        let path = dir.join("large.bin");
+        std::fs::write(&path, [0; 0x11]).unwrap();
+        match ROM::load(0x10, Endian::Little, &path) {
+            Err(RomError::TooLarge {
+                path: error_path,
+                expected,
+                actual,
+            }) => assert_eq!((error_path, expected, actual), (path, 0x10, 0x11)),
+            _ => panic!("expected TooLarge"),
+        }
+    }
+}
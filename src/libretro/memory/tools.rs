@@ -0,0 +1,224 @@
+use crate::memory::Memory;
+use std::str::FromStr;
+
+fn strip_hex_prefix(s: &str) -> &str {
+    s.strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s)
+}
+
+pub fn parse_byte(s: &str) -> Result<u8, String> {
+    u8::from_str_radix(strip_hex_prefix(s), 16).map_err(|_| format!("Invalid byte: {}", s))
+}
+
+pub fn parse_addr(s: &str) -> Result<usize, String> {
+    usize::from_str_radix(strip_hex_prefix(s), 16).map_err(|_| format!("Invalid address: {}", s))
+}
+
+/// Parses an address where `x` digits are wildcards, e.g. `C0xx` is `$C000-$C0FF`
+fn parse_wildcard_addr(s: &str) -> Result<(usize, usize), String> {
+    let digits = strip_hex_prefix(s);
+    let low = digits.replace(['x', 'X'], "0");
+    let high = digits.replace(['x', 'X'], "F");
+    match (
+        usize::from_str_radix(&low, 16),
+        usize::from_str_radix(&high, 16),
+    ) {
+        (Ok(low), Ok(high)) => Ok((low, high)),
+        _ => Err(format!("Invalid address: {}", s)),
+    }
+}
+
+/// Parses an inclusive range written as `<addr>-<addr>` or a wildcard address like `C0xx`
+pub fn parse_range(s: &str) -> Result<(usize, usize), String> {
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (parse_wildcard_addr(start)?.0, parse_wildcard_addr(end)?.1),
+        None => parse_wildcard_addr(s)?,
+    };
+    if end < start {
+        return Err(format!("Invalid range: {}", s));
+    }
+    Ok((start, end))
+}
+
+/// A byte to search for, only comparing the bits in `mask`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PatternByte {
+    pub value: u8,
+    pub mask: u8,
+}
+
+impl PatternByte {
+    pub fn matches(&self, data: u8) -> bool {
+        data & self.mask == self.value & self.mask
+    }
+}
+
+/// A sequence of bytes to search memory for
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern(pub Vec<PatternByte>);
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    /// Parses hex bytes with `??` wildcards, e.g. `A9 ?? 8D`, and quoted strings, e.g.
+    /// `"SYNTAX"`, which match Apple text with or without the high bit set
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = Vec::new();
+        let mut rest = s.trim_start();
+        while !rest.is_empty() {
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let Some((text, after)) = quoted.split_once('"') else {
+                    return Err(String::from("Unterminated string"));
+                };
+                for c in text.chars() {
+                    if !c.is_ascii() {
+                        return Err(format!("Invalid character: {}", c));
+                    }
+                    bytes.push(PatternByte {
+                        value: c as u8,
+                        mask: 0x7F,
+                    });
+                }
+                rest = after;
+            } else {
+                let end = rest.find([' ', '"']).unwrap_or(rest.len());
+                let byte = match &rest[..end] {
+                    "??" => PatternByte { value: 0, mask: 0 },
+                    x => PatternByte {
+                        value: parse_byte(x)?,
+                        mask: 0xFF,
+                    },
+                };
+                bytes.push(byte);
+                rest = &rest[end..];
+            }
+            rest = rest.trim_start();
+        }
+
+        if bytes.is_empty() {
+            return Err(String::from("Empty pattern"));
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// Checks that the inclusive range `start..=end` is inside `mem`
+fn check_range(mem: &dyn Memory, start: usize, end: usize) -> Result<(), String> {
+    if end < mem.size() {
+        Ok(())
+    } else {
+        Err(format!(
+            "${:04X}-${:04X} is outside memory, which ends at ${:04X}",
+            start,
+            end,
+            mem.size() - 1
+        ))
+    }
+}
+
+/// Reads the inclusive range `start..=end` without side effects
+pub fn dump(mem: &dyn Memory, start: usize, end: usize) -> Result<Vec<u8>, String> {
+    check_range(mem, start, end)?;
+    Ok((start..=end).map(|addr| mem.peek(addr)).collect())
+}
+
+/// Writes `data` starting at `addr`
+pub fn load(mem: &mut dyn Memory, addr: usize, data: &[u8]) -> Result<(), String> {
+    check_range(mem, addr, addr + data.len().max(1) - 1)?;
+    for (i, &byte) in data.iter().enumerate() {
+        mem.write(addr + i, byte);
+    }
+    Ok(())
+}
+
+/// Fills the inclusive range `start..=end` by repeating `pattern`
+pub fn fill(mem: &mut dyn Memory, start: usize, end: usize, pattern: &[u8]) -> Result<(), String> {
+    check_range(mem, start, end)?;
+    for (addr, &byte) in (start..=end).zip(pattern.iter().cycle()) {
+        mem.write(addr, byte);
+    }
+    Ok(())
+}
+
+/// Finds every address in `start..=end` where `pattern` begins
+pub fn search(
+    mem: &dyn Memory,
+    start: usize,
+    end: usize,
+    pattern: &Pattern,
+) -> Result<Vec<usize>, String> {
+    let data = dump(mem, start, end)?;
+    Ok(data
+        .windows(pattern.0.len())
+        .enumerate()
+        .filter(|(_, window)| pattern.0.iter().zip(*window).all(|(p, &b)| p.matches(b)))
+        .map(|(i, _)| start + i)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::tools::{Pattern, PatternByte, dump, fill, load, parse_range, search};
+    use crate::memory::{Endian, RAM};
+
+    #[test]
+    fn pattern_parse() {
+        let wild = PatternByte { value: 0, mask: 0 };
+        let byte = |value| PatternByte { value, mask: 0xFF };
+        let text = |c: char| PatternByte {
+            value: c as u8,
+            mask: 0x7F,
+        };
+
+        assert_eq!(
+            "A9 ?? $8D".parse(),
+            Ok(Pattern(vec![byte(0xA9), wild, byte(0x8D)]))
+        );
+        assert_eq!(
+            "\"HI\" 00".parse(),
+            Ok(Pattern(vec![text('H'), text('I'), byte(0x00)]))
+        );
+        assert!("\"HI".parse::<Pattern>().is_err());
+        assert!("GG".parse::<Pattern>().is_err());
+        assert!("".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn memory_tools() {
+        let mut mem = RAM::new(0x100, Endian::Little);
+        fill(&mut mem, 0x10, 0x14, &[0xAA, 0xBB]).unwrap();
+        assert_eq!(
+            dump(&mem, 0x10, 0x15),
+            Ok(vec![0xAA, 0xBB, 0xAA, 0xBB, 0xAA, 0x00])
+        );
+
+        // "HI" in high-bit ASCII, then in plain ASCII
+        load(&mut mem, 0x20, &[0xC8, 0xC9, 0x00, 0x48, 0x49]).unwrap();
+        let hi: Pattern = "\"HI\"".parse().unwrap();
+        assert_eq!(search(&mem, 0, 0xFF, &hi), Ok(vec![0x20, 0x23]));
+
+        let wild: Pattern = "AA ?? AA".parse().unwrap();
+        assert_eq!(search(&mem, 0, 0xFF, &wild), Ok(vec![0x10, 0x12]));
+        assert_eq!(search(&mem, 0x11, 0xFF, &wild), Ok(vec![0x12]));
+    }
+
+    #[test]
+    fn memory_tools_bounds() {
+        let mut mem = RAM::new(0x100, Endian::Little);
+        assert!(dump(&mem, 0, 0xFFFF_FFFF).is_err());
+        assert!(search(&mem, 0xF0, 0x100, &"00".parse().unwrap()).is_err());
+        assert!(fill(&mut mem, 0, 0x100, &[0]).is_err());
+        assert!(load(&mut mem, 0xFE, &[1, 2, 3]).is_err());
+        assert_eq!(load(&mut mem, 0xFE, &[1, 2]), Ok(()));
+    }
+
+    #[test]
+    fn range_parse() {
+        assert_eq!(parse_range("$0800-$08FF"), Ok((0x800, 0x8FF)));
+        assert_eq!(parse_range("C0xx"), Ok((0xC000, 0xC0FF)));
+        assert_eq!(parse_range("1234"), Ok((0x1234, 0x1234)));
+        assert!(parse_range("2000-1000").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::memory::tools::{parse_byte, parse_range};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Kind of bus access a watchpoint triggers on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+/// Condition on the value being read or written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchCondition {
+    /// Value is equal to the byte
+    Equals(u8),
+    /// Value has any of the bits in the mask set
+    Mask(u8),
+}
+
+/// A watched address range, inclusive on both ends
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub start: usize,
+    pub end: usize,
+    pub condition: Option<WatchCondition>,
+}
+
+/// A single access that matched a watchpoint
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub kind: WatchKind,
+    pub addr: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl Watchpoint {
+    pub fn new(
+        kind: WatchKind,
+        start: usize,
+        end: usize,
+        condition: Option<WatchCondition>,
+    ) -> Self {
+        Self {
+            kind,
+            start,
+            end,
+            condition,
+        }
+    }
+
+    /// Checks an access of `kind` (either `Read` or `Write`) against this watchpoint
+    pub fn matches(&self, kind: WatchKind, addr: usize, value: u8) -> bool {
+        if self.kind != WatchKind::Access && self.kind != kind {
+            return false;
+        }
+        if addr < self.start || addr > self.end {
+            return false;
+        }
+        match self.condition {
+            None => true,
+            Some(WatchCondition::Equals(v)) => value == v,
+            Some(WatchCondition::Mask(m)) => value & m != 0,
+        }
+    }
+}
+
+impl Display for WatchKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Access => write!(f, "access"),
+        }
+    }
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ${:04X}", self.kind, self.start)?;
+        if self.end != self.start {
+            write!(f, "-${:04X}", self.end)?;
+        }
+        match self.condition {
+            None => Ok(()),
+            Some(WatchCondition::Equals(v)) => write!(f, " == ${:02X}", v),
+            Some(WatchCondition::Mask(m)) => write!(f, " & ${:02X}", m),
+        }
+    }
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            WatchKind::Write => write!(
+                f,
+                "Watchpoint #{}: write ${:04X}: ${:02X} -> ${:02X}",
+                self.id, self.addr, self.old, self.new
+            ),
+            _ => write!(
+                f,
+                "Watchpoint #{}: read ${:04X}: ${:02X}",
+                self.id, self.addr, self.new
+            ),
+        }
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    /// Parses `<r|w|rw> <addr>[-<addr>] [== <byte> | & <byte>]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+        let (kind, range, cond) = match args.as_slice() {
+            [kind, range, cond @ ..] => (kind, range, cond),
+            _ => {
+                return Err(String::from(
+                    "Expected <r|w|rw> <addr>[-<addr>] [== <byte> | & <byte>]",
+                ));
+            }
+        };
+
+        let kind = match *kind {
+            "r" | "read" => WatchKind::Read,
+            "w" | "write" => WatchKind::Write,
+            "rw" | "a" | "access" => WatchKind::Access,
+            _ => return Err(format!("Invalid watch kind: {}", kind)),
+        };
+
+        let (start, end) = parse_range(range)?;
+
+        let condition = match cond {
+            [] => None,
+            ["==", v] => Some(WatchCondition::Equals(parse_byte(v)?)),
+            ["&", m] => Some(WatchCondition::Mask(parse_byte(m)?)),
+            _ => return Err(format!("Invalid condition: {}", cond.join(" "))),
+        };
+
+        Ok(Self::new(kind, start, end, condition))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::{WatchCondition, WatchKind, Watchpoint};
+
+    #[test]
+    fn watchpoint_parse() {
+        assert_eq!(
+            "w $C0xx == $00".parse::<Watchpoint>(),
+            Ok(Watchpoint::new(
+                WatchKind::Write,
+                0xC000,
+                0xC0FF,
+                Some(WatchCondition::Equals(0))
+            ))
+        );
+        assert_eq!(
+            "r 1234 & 80".parse::<Watchpoint>(),
+            Ok(Watchpoint::new(
+                WatchKind::Read,
+                0x1234,
+                0x1234,
+                Some(WatchCondition::Mask(0x80))
+            ))
+        );
+        assert_eq!(
+            "rw 0x300-0x3FF".parse::<Watchpoint>(),
+            Ok(Watchpoint::new(WatchKind::Access, 0x300, 0x3FF, None))
+        );
+        assert!("w 2000-1000".parse::<Watchpoint>().is_err());
+        assert!("x 1234".parse::<Watchpoint>().is_err());
+        assert!("r 1234 > 5".parse::<Watchpoint>().is_err());
+    }
+
+    #[test]
+    fn watchpoint_matches() {
+        let wp = Watchpoint::new(
+            WatchKind::Write,
+            0xC000,
+            0xC0FF,
+            Some(WatchCondition::Equals(0)),
+        );
+        assert!(wp.matches(WatchKind::Write, 0xC030, 0x00));
+        assert!(!wp.matches(WatchKind::Write, 0xC030, 0x01));
+        assert!(!wp.matches(WatchKind::Read, 0xC030, 0x00));
+        assert!(!wp.matches(WatchKind::Write, 0xC100, 0x00));
+
+        let wp = Watchpoint::new(
+            WatchKind::Access,
+            0x10,
+            0x10,
+            Some(WatchCondition::Mask(0x80)),
+        );
+        assert!(wp.matches(WatchKind::Read, 0x10, 0x81));
+        assert!(wp.matches(WatchKind::Write, 0x10, 0x80));
+        assert!(!wp.matches(WatchKind::Read, 0x10, 0x7F));
+    }
+}
@@ -15,10 +15,15 @@ pub mod machine;
 // pub use memory::Memory;
 // pub use debug::Debugger;
 
+use machine::WatchReport;
+use machine::apple_iie_e_display::TextScreen;
+use memory::Watchpoint;
+use memory::tools::Pattern;
+use std::path::PathBuf;
 
 pub enum DisplayCommand {
-    Write(usize, u8),
-    Redraw,
+    Frame(Vec<u8>),
+    Text(TextScreen),
     Exit(u8),
 }
 
@@ -28,5 +33,28 @@ pub enum EmulatorCommand {
     Run,
     Stop,
     Reset,
+    Watch(Watchpoint),
+    Unwatch(usize),
+    ListWatches,
+    ShowMap,
+    Dump(usize, usize, PathBuf),
+    Load(usize, PathBuf),
+    Fill(usize, usize, Vec<u8>),
+    Search(usize, usize, Pattern),
+    StartHeatmap,
+    SaveHeatmap(PathBuf),
+    Screenshot(PathBuf),
+    ShowScreen,
+    StartRecording(PathBuf),
+    StopRecording,
+    KeyDown(u8),
+    KeyUp,
+    AppleKeys(bool, bool),
+    Quit,
+}
+
+pub enum TerminalCommand {
+    Output(String),
+    WatchHits(Vec<WatchReport>),
 }
 
@@ -1,41 +1,93 @@
 mod config;
 
-use crate::config::Config;
 use crate::config::Machines::AppleIiE;
-use libretro::machine::apple_iie_e_display::{
-    DISPLAY_HEIGHT, DISPLAY_SCALE, DISPLAY_WIDTH, Display,
-};
+use crate::config::{Command, Config, DisplayKind, Machines};
+use libretro::machine::apple_ii_roms::RomInfo;
+use libretro::machine::apple_iie_e_display::{DISPLAY_HEIGHT, DISPLAY_WIDTH, Renderer};
+use libretro::machine::framebuffer::Framebuffer;
+use libretro::machine::keyboard::{HostKey, apple_key};
+use libretro::machine::monitor::{Monitor, Palette};
+use libretro::machine::recorder::Recorder;
+use libretro::machine::viewport::{Scaling, Viewport};
 use libretro::machine::{AppleIIe, Machine};
-use libretro::{DisplayCommand, EmulatorCommand};
+use libretro::memory::tools::{self, parse_addr, parse_byte, parse_range};
+use libretro::{DisplayCommand, EmulatorCommand, TerminalCommand};
 use log::{error, info};
 use pixels::{Pixels, SurfaceTexture};
-use std::io::Write;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, mpsc};
 use std::thread;
 use std::thread::sleep;
 use std::time::Duration;
 use tao::dpi::LogicalSize;
-use tao::event::{Event, WindowEvent};
+use tao::event::{ElementState, Event, WindowEvent};
 use tao::event_loop::{ControlFlow, EventLoop};
-use tao::window::WindowBuilder;
+use tao::keyboard::{Key, KeyCode, ModifiersState};
+use tao::window::{Fullscreen, WindowBuilder};
 
 fn start_emulation_thread(
     config: Config,
+    machine: Machines,
     cmd_rx: mpsc::Receiver<EmulatorCommand>,
     gui_tx: mpsc::Sender<DisplayCommand>,
-) {
+    term_tx: mpsc::Sender<TerminalCommand>,
+) -> Result<(), String> {
     let (cycle_tx, cycle_rx) = mpsc::channel::<()>();
-    let (draw_tx, draw_rx) = mpsc::channel::<()>();
-    let gui2 = gui_tx.clone();
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
 
     thread::spawn(move || {
-        let mut mach: Box<dyn Machine> = match config.machine {
+        let model = machine.model();
+        let mut mach: Box<dyn Machine> = match machine {
             AppleIiE {
                 ref disk1,
                 ref disk2,
                 freq,
+                ram_init,
+                monochrome,
+                renderer,
+                phosphor,
+                scanlines,
+                ref palette,
+                ..
             } => {
-                let mut x = AppleIIe::new(gui_tx);
+                info!("RAM init: {}", ram_init);
+                info!("Renderer: {}", renderer);
+                let mut x = match AppleIIe::new(&config.rom_paths(), model, ram_init, renderer) {
+                    Ok(x) => x,
+                    Err(err) => {
+                        ready_tx.send(Err(err.to_string())).unwrap();
+                        return;
+                    }
+                };
+                x.set_monochrome(monochrome);
+                if let Some(path) = palette {
+                    let palette = match renderer {
+                        Renderer::Rgb => config
+                            .get_file_string(path)
+                            .map_err(|x| x.to_string())
+                            .and_then(|s| s.parse::<Palette>())
+                            .map_err(|err| {
+                                format!("Cannot load palette {}: {}", path.display(), err)
+                            }),
+                        Renderer::Ntsc(_) => {
+                            Err(String::from("A palette only applies to the rgb renderer"))
+                        }
+                    };
+                    match palette {
+                        Ok(palette) => x.set_palette(palette),
+                        Err(err) => {
+                            ready_tx.send(Err(err)).unwrap();
+                            return;
+                        }
+                    }
+                }
+                x.set_monitor(Monitor {
+                    phosphor,
+                    scanlines,
+                });
                 if let Some(disk1) = disk1 {
                     x.load_disk1(config.get_file(disk1).expect("Failed to load disk1"));
                 }
@@ -51,51 +103,356 @@ fn start_emulation_thread(
                         cycle_tx.send(()).unwrap();
                     }
                 });
-                thread::spawn(move || {
-                    info!("Video Freq: 1khz");
-                    loop {
-                        sleep(Duration::from_millis(1));
-                        draw_tx.send(()).unwrap();
-                    }
-                });
                 Box::new(x)
             }
         };
+        ready_tx.send(Ok(())).unwrap();
+
+        let mut is_running = config.screenshot_at_cycle.is_some();
+        let mut screenshot_at = config.screenshot_at_cycle;
+        let mut frame = Framebuffer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        let mut recording: Option<Recording> = None;
+
+        // Reports any watchpoint hits to the terminal, returning true if there were any
+        let report_watch_hits = |mach: &mut Box<dyn Machine>| {
+            let hits = mach.take_watch_hits();
+            if hits.is_empty() {
+                return false;
+            }
+            term_tx.send(TerminalCommand::WatchHits(hits)).unwrap();
+            true
+        };
 
-        let mut is_running = false;
+        // Only the window shows pixels, the terminal shows text and headless nothing,
+        // so otherwise frames are only drawn for a recording
+        let display = config.display;
+        let shows_frames = display == DisplayKind::Window;
+        let display_command = |mach: &dyn Machine, frame: &Framebuffer| match display {
+            DisplayKind::Window => Some(DisplayCommand::Frame(frame.data().to_vec())),
+            DisplayKind::Tty => Some(DisplayCommand::Text(mach.text_screen())),
+            DisplayKind::Headless => None,
+        };
 
         loop {
             while let Ok(()) = cycle_rx.try_recv() {
                 if is_running {
                     mach.as_mut().cycle();
+                    if report_watch_hits(&mut mach) {
+                        is_running = false;
+                    }
+                }
+                if let Some(cycle) = screenshot_at
+                    && mach.clock() >= cycle
+                {
+                    screenshot_at = None;
+                    mach.render(frame.data_mut());
+                    let saved = save_screenshot(&frame, &config.screenshot);
+                    if config.display == DisplayKind::Headless {
+                        quit(&gui_tx, recording.take(), saved);
+                    } else {
+                        let msg = saved.unwrap_or_else(|err| err);
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
                 }
             }
-            while let Ok(()) = draw_rx.try_recv() {
-                gui2.send(DisplayCommand::Redraw).unwrap();
+            if mach.take_frame_done() {
+                if shows_frames || recording.is_some() {
+                    mach.render(frame.data_mut());
+                }
+                if let Some((recorder, path)) = &mut recording
+                    && let Err(err) = recorder.write_frame(&frame)
+                {
+                    let msg = format!(
+                        "Stopped recording, cannot write {}: {}",
+                        path.display(),
+                        err
+                    );
+                    term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    recording = None;
+                }
+                if let Some(cmd) = display_command(mach.as_ref(), &frame) {
+                    gui_tx.send(cmd).unwrap();
+                }
             }
             while let Ok(cmd) = cmd_rx.try_recv() {
                 match cmd {
-                    EmulatorCommand::Cycle => mach.as_mut().cycle(),
-                    EmulatorCommand::Step => mach.as_mut().step(),
+                    EmulatorCommand::Cycle => {
+                        mach.as_mut().cycle();
+                        report_watch_hits(&mut mach);
+                    }
+                    EmulatorCommand::Step => {
+                        mach.as_mut().step();
+                        report_watch_hits(&mut mach);
+                    }
                     EmulatorCommand::Run => is_running = true,
                     EmulatorCommand::Stop => is_running = false,
                     EmulatorCommand::Reset => mach.as_mut().reset(),
+                    EmulatorCommand::Watch(wp) => {
+                        let desc = wp.to_string();
+                        let id = mach.get_memory().add_watchpoint(wp);
+                        let msg = format!("Watchpoint #{}: {}", id, desc);
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::Unwatch(id) => {
+                        let msg = match mach.get_memory().remove_watchpoint(id) {
+                            Some(_) => format!("Removed watchpoint #{}", id),
+                            None => format!("No watchpoint #{}", id),
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::ListWatches => {
+                        let msg = mach
+                            .get_memory()
+                            .watchpoints()
+                            .iter()
+                            .map(|(id, wp)| format!("#{}: {}", id, wp))
+                            .collect::<Vec<String>>()
+                            .join("\n");
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::ShowMap => {
+                        let msg = mach
+                            .get_memory()
+                            .page_map()
+                            .iter()
+                            .map(|x| x.to_string())
+                            .collect::<Vec<String>>()
+                            .join("\n");
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::Dump(start, end, path) => {
+                        let msg = match tools::dump(mach.get_memory(), start, end) {
+                            Ok(data) => match std::fs::write(&path, data) {
+                                Ok(()) => format!(
+                                    "Saved ${:04X}-${:04X} to {}",
+                                    start,
+                                    end,
+                                    path.display()
+                                ),
+                                Err(err) => format!("Cannot write {}: {}", path.display(), err),
+                            },
+                            Err(err) => err,
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::Load(addr, path) => {
+                        let msg = match std::fs::read(&path) {
+                            Ok(data) => match tools::load(mach.get_memory(), addr, &data) {
+                                Ok(()) => {
+                                    format!("Loaded {:#X} bytes at ${:04X}", data.len(), addr)
+                                }
+                                Err(err) => err,
+                            },
+                            Err(err) => format!("Cannot read {}: {}", path.display(), err),
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::Fill(start, end, data) => {
+                        let msg = match tools::fill(mach.get_memory(), start, end, &data) {
+                            Ok(()) => format!("Filled ${:04X}-${:04X}", start, end),
+                            Err(err) => err,
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::Search(start, end, pattern) => {
+                        let msg = match tools::search(mach.get_memory(), start, end, &pattern) {
+                            Ok(found) => {
+                                let addrs: Vec<String> =
+                                    found.iter().map(|x| format!("${:04X}", x)).collect();
+                                let mut msg = format!("Found {} matches", found.len());
+                                for line in addrs.chunks(8) {
+                                    msg.push_str(&format!("\n  {}", line.join(" ")));
+                                }
+                                msg
+                            }
+                            Err(err) => err,
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::StartHeatmap => {
+                        mach.get_memory().start_heatmap();
+                        let msg = String::from("Counting memory accesses");
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::SaveHeatmap(path) => {
+                        let msg = match mach.get_memory().stop_heatmap() {
+                            None => String::from("Heatmap not started"),
+                            Some(heatmap) => match heatmap.save(&path) {
+                                Ok(()) => format!("Saved heatmap to {}", path.display()),
+                                Err(err) => format!("Cannot write {}: {}", path.display(), err),
+                            },
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::StartRecording(path) => {
+                        let msg = match Recorder::create(&path, frame.width(), frame.height()) {
+                            Ok(recorder) => {
+                                let msg = format!("Recording to {}", path.display());
+                                recording = Some((recorder, path));
+                                msg
+                            }
+                            Err(err) => format!("Cannot write {}: {}", path.display(), err),
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::StopRecording => {
+                        let msg = match recording.take() {
+                            None => String::from("Not recording"),
+                            Some((recorder, path)) => {
+                                finish_recording(recorder, &path).unwrap_or_else(|err| err)
+                            }
+                        };
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::ShowScreen => {
+                        let msg = mach.screen_text().join("\n");
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::Quit => quit(&gui_tx, recording.take(), Ok(String::new())),
+                    EmulatorCommand::Screenshot(path) => {
+                        mach.render(frame.data_mut());
+                        let msg = save_screenshot(&frame, &path).unwrap_or_else(|err| err);
+                        term_tx.send(TerminalCommand::Output(msg)).unwrap();
+                    }
+                    EmulatorCommand::KeyDown(code) => mach.key_down(code),
+                    EmulatorCommand::KeyUp => mach.key_up(),
+                    EmulatorCommand::AppleKeys(open, solid) => mach.set_apple_keys(open, solid),
                 };
+                // Accesses made on behalf of the terminal should not trip watchpoints
+                mach.get_memory().take_watch_hits();
+
+                // The beam only moves while the CPU runs, so show changes made while stopped
+                if !is_running {
+                    mach.redraw();
+                    if shows_frames {
+                        mach.render(frame.data_mut());
+                    }
+                    if let Some(cmd) = display_command(mach.as_ref(), &frame) {
+                        gui_tx.send(cmd).unwrap();
+                    }
+                }
+            }
+
+            // A watchpoint or stop command means the screenshot cycle will never come
+            if !is_running
+                && config.display == DisplayKind::Headless
+                && let Some(cycle) = screenshot_at.take()
+            {
+                let err = format!(
+                    "Stopped at cycle {} before the screenshot at cycle {}",
+                    mach.clock(),
+                    cycle
+                );
+                quit(&gui_tx, recording.take(), Err(err));
             }
         }
     });
+
+    ready_rx.recv().unwrap()
+}
+
+type Recording = (Recorder<BufWriter<File>>, PathBuf);
+
+fn finish_recording(recorder: Recorder<BufWriter<File>>, path: &Path) -> Result<String, String> {
+    let frames = recorder.frames();
+    match recorder.finish() {
+        Ok(_) => Ok(format!("Saved {} frames to {}", frames, path.display())),
+        Err(err) => Err(format!("Cannot write {}: {}", path.display(), err)),
+    }
+}
+
+/// Finishes any recording and prints the result of the run, then tells the
+/// display to exit with its status
+///
+/// The display exits the process straight away, so everything must be written first.
+fn quit(
+    gui_tx: &mpsc::Sender<DisplayCommand>,
+    recording: Option<Recording>,
+    result: Result<String, String>,
+) {
+    let finished = recording.map(|(recorder, path)| finish_recording(recorder, &path));
+    let mut code = 0;
+    for result in finished.into_iter().chain([result]) {
+        match result {
+            Ok(msg) if msg.is_empty() => {}
+            Ok(msg) => println!("{}", msg),
+            Err(err) => {
+                eprintln!("{}", err);
+                code = 1;
+            }
+        }
+    }
+    std::io::stdout().flush().unwrap();
+    gui_tx.send(DisplayCommand::Exit(code)).unwrap();
+}
+
+fn save_screenshot(frame: &Framebuffer, path: &Path) -> Result<String, String> {
+    match frame.save_png(path) {
+        Ok(()) => Ok(format!("Saved screenshot to {}", path.display())),
+        Err(err) => Err(format!("Cannot write {}: {}", path.display(), err)),
+    }
+}
+
+/// Prints a message from the emulator, returning true if emulation stopped
+fn print_terminal_command(cmd: TerminalCommand) -> bool {
+    match cmd {
+        TerminalCommand::Output(msg) => {
+            if !msg.is_empty() {
+                println!("{}", msg);
+            }
+            false
+        }
+        TerminalCommand::WatchHits(hits) => {
+            println!();
+            for hit in hits {
+                println!("{}", hit);
+            }
+            true
+        }
+    }
+}
+
+/// Blocks until the emulator replies with output for the last command
+fn wait_for_output(term_rx: &mpsc::Receiver<TerminalCommand>) {
+    while let Ok(cmd) = term_rx.recv() {
+        if let TerminalCommand::Output(_) = cmd {
+            print_terminal_command(cmd);
+            break;
+        }
+        print_terminal_command(cmd);
+    }
+}
+
+/// Returns what follows the first `n` whitespace separated arguments of `input`
+fn skip_args(input: &str, n: usize) -> &str {
+    let mut rest = input.trim_start();
+    for _ in 0..n {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest.trim_end()
 }
 
 fn start_terminal_thread(
     cmd_tx: mpsc::Sender<EmulatorCommand>,
-    gui_tx: mpsc::Sender<DisplayCommand>,
+    term_rx: mpsc::Receiver<TerminalCommand>,
+    running: bool,
 ) {
     thread::spawn(move || {
         use std::io::{BufRead, BufReader};
         let stdin = std::io::stdin();
         let mut reader = BufReader::new(stdin.lock());
 
-        let mut is_running = false;
+        let mut is_running = running;
+
+        // Sends a command and prints the emulator's reply
+        let query = |cmd: Result<EmulatorCommand, String>| match cmd {
+            Ok(cmd) => {
+                cmd_tx.send(cmd).unwrap();
+                wait_for_output(&term_rx);
+            }
+            Err(err) => println!("{}", err),
+        };
 
         let (ctrlc_tx, ctrlc_rx) = mpsc::channel::<()>();
         ctrlc::set_handler(move || {
@@ -109,6 +466,12 @@ fn start_terminal_thread(
                 cmd_tx.send(EmulatorCommand::Stop).unwrap();
             }
 
+            while let Ok(cmd) = term_rx.try_recv() {
+                if print_terminal_command(cmd) {
+                    is_running = false;
+                }
+            }
+
             if !is_running {
                 print!("retro> ");
                 std::io::stdout().flush().unwrap();
@@ -117,79 +480,268 @@ fn start_terminal_thread(
                     if n == 0 {
                         break;
                     }
-                    match input.trim() {
-                        "continue" | "c" => {
+                    let args: Vec<&str> = input.split_whitespace().collect();
+                    match args.as_slice() {
+                        ["continue"] | ["c"] => {
                             is_running = true;
                             cmd_tx.send(EmulatorCommand::Run).unwrap()
                         }
-                        "step" | "s" => cmd_tx.send(EmulatorCommand::Step).unwrap(),
-                        "cycle" => cmd_tx.send(EmulatorCommand::Cycle).unwrap(),
-                        "reset" | "r" => cmd_tx.send(EmulatorCommand::Reset).unwrap(),
-                        "exit" | "quit" | "q" => break,
-                        "" => {}
+                        ["step"] | ["s"] => cmd_tx.send(EmulatorCommand::Step).unwrap(),
+                        ["cycle"] => cmd_tx.send(EmulatorCommand::Cycle).unwrap(),
+                        ["reset"] | ["r"] => cmd_tx.send(EmulatorCommand::Reset).unwrap(),
+                        ["watch"] | ["watches"] => query(Ok(EmulatorCommand::ListWatches)),
+                        ["watch", rest @ ..] => {
+                            query(rest.join(" ").parse().map(EmulatorCommand::Watch))
+                        }
+                        ["unwatch", id] => query(
+                            id.parse()
+                                .map(EmulatorCommand::Unwatch)
+                                .map_err(|_| format!("Invalid watchpoint: {}", id)),
+                        ),
+                        ["map"] => query(Ok(EmulatorCommand::ShowMap)),
+                        ["screen"] => query(Ok(EmulatorCommand::ShowScreen)),
+                        ["dump", range, file] => query(
+                            parse_range(range)
+                                .map(|(start, end)| EmulatorCommand::Dump(start, end, file.into())),
+                        ),
+                        ["load", file, addr] => query(
+                            parse_addr(addr).map(|addr| EmulatorCommand::Load(addr, file.into())),
+                        ),
+                        ["fill", range, bytes @ ..] if !bytes.is_empty() => {
+                            query(parse_range(range).and_then(|(start, end)| {
+                                let data: Result<Vec<u8>, String> =
+                                    bytes.iter().map(|x| parse_byte(x)).collect();
+                                Ok(EmulatorCommand::Fill(start, end, data?))
+                            }))
+                        }
+                        ["search", range, _, ..] => {
+                            query(parse_range(range).and_then(|(start, end)| {
+                                let pattern = skip_args(&input, 2).parse()?;
+                                Ok(EmulatorCommand::Search(start, end, pattern))
+                            }))
+                        }
+                        ["heatmap", "start"] => query(Ok(EmulatorCommand::StartHeatmap)),
+                        ["heatmap", "save", file] => {
+                            query(Ok(EmulatorCommand::SaveHeatmap(file.into())))
+                        }
+                        ["record", "stop"] => query(Ok(EmulatorCommand::StopRecording)),
+                        ["record", file] => query(Ok(EmulatorCommand::StartRecording(file.into()))),
+                        ["screenshot", file] => query(Ok(EmulatorCommand::Screenshot(file.into()))),
+                        ["exit"] | ["quit"] | ["q"] => break,
+                        [] => {}
                         _ => println!("Unknown command!"),
                     }
                 }
             }
         }
-        gui_tx.send(DisplayCommand::Exit(0)).unwrap();
+
+        // The emulator exits once it has finished writing, so keep printing until then
+        cmd_tx.send(EmulatorCommand::Quit).unwrap();
+        for cmd in term_rx {
+            print_terminal_command(cmd);
+        }
     });
 }
 
-fn start_display_thread(gui_rx: mpsc::Receiver<DisplayCommand>) {
+/// Draws the text screen above the terminal prompt until told to exit
+///
+/// The prompt and emulator output scroll in a region below the screen.
+fn run_tty(gui_rx: mpsc::Receiver<DisplayCommand>) {
+    print!("\x1b[2J\x1b[26r\x1b[26;1H");
+    std::io::stdout().flush().unwrap();
+    let mut last = None;
+    for cmd in gui_rx {
+        match cmd {
+            DisplayCommand::Text(screen) if last.as_ref() != Some(&screen) => {
+                print!("\x1b7{}\x1b8", screen.to_ansi());
+                std::io::stdout().flush().unwrap();
+                last = Some(screen);
+            }
+            DisplayCommand::Exit(code) => {
+                print!("\x1b[r");
+                std::io::stdout().flush().unwrap();
+                std::process::exit(code as i32);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Discards frames until told to exit, for running without a window
+fn run_headless(gui_rx: mpsc::Receiver<DisplayCommand>) {
+    for cmd in gui_rx {
+        if let DisplayCommand::Exit(code) = cmd {
+            std::process::exit(code as i32);
+        }
+    }
+}
+
+/// Host key for a tao key, if the //e has one like it
+fn host_key(key: &Key) -> Option<HostKey> {
+    Some(match key {
+        Key::Character(s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => HostKey::Char(c),
+                _ => return None,
+            }
+        }
+        Key::Space => HostKey::Space,
+        Key::Enter => HostKey::Enter,
+        Key::Tab => HostKey::Tab,
+        Key::Escape => HostKey::Escape,
+        Key::Delete => HostKey::Delete,
+        Key::Backspace => HostKey::Backspace,
+        Key::ArrowLeft => HostKey::Left,
+        Key::ArrowRight => HostKey::Right,
+        Key::ArrowUp => HostKey::Up,
+        Key::ArrowDown => HostKey::Down,
+        _ => return None,
+    })
+}
+
+fn start_display_thread(
+    gui_rx: mpsc::Receiver<DisplayCommand>,
+    cmd_tx: mpsc::Sender<EmulatorCommand>,
+    mut viewport: Viewport,
+    viewport_path: PathBuf,
+) {
+    const FRAME: (u32, u32) = (DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+
     let event_loop = EventLoop::new();
     let window = {
-        let size = LogicalSize::new(
-            DISPLAY_WIDTH as u32 * DISPLAY_SCALE,
-            DISPLAY_HEIGHT as u32 * DISPLAY_SCALE,
-        );
+        let (width, height) = viewport.window_size(FRAME);
         let window = WindowBuilder::new()
             .with_title("RetroEmu")
-            .with_inner_size(size)
-            .with_min_inner_size(size)
+            .with_inner_size(LogicalSize::new(width, height))
+            .with_min_inner_size(LogicalSize::new(FRAME.0 / 2, FRAME.1 / 2))
+            .with_resizable(true)
             .build(&event_loop)
             .unwrap();
         Arc::new(window)
     };
-    window.set_resizable(false);
 
+    // The buffer matches the window so frames can be scaled by any amount
+    let mut size = window.inner_size();
     let mut pixels = {
-        let window_size = window.inner_size();
-        let surface_texture =
-            SurfaceTexture::new(window_size.width, window_size.height, Arc::clone(&window));
-        Pixels::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32, surface_texture)
-            .expect("Failed to create pixels")
+        let surface_texture = SurfaceTexture::new(size.width, size.height, Arc::clone(&window));
+        Pixels::new(size.width, size.height, surface_texture).expect("Failed to create pixels")
+    };
+    let mut frame = vec![0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+
+    // Open Apple and Solid Apple are the left and right Alt keys
+    let mut modifiers = ModifiersState::default();
+    let mut apple_keys = (false, false);
+    let mut held_keys = HashSet::new();
+
+    let save = move |viewport: &Viewport| {
+        if let Err(err) = std::fs::write(&viewport_path, viewport.to_string()) {
+            error!("Cannot write {}: {}", viewport_path.display(), err);
+        }
     };
-    let display = Display::new();
 
     event_loop.run(move |event, _, control_flow| {
+        let mut redraw = false;
         while let Ok(cmd) = gui_rx.try_recv() {
             match cmd {
-                DisplayCommand::Write(addr, data) => {
-                    display.write_text(pixels.frame_mut(), addr, data as char);
-                }
-                DisplayCommand::Redraw => {
-                    window.request_redraw();
+                DisplayCommand::Frame(data) => {
+                    frame = data;
+                    redraw = true;
                 }
+                DisplayCommand::Text(_) => {}
                 DisplayCommand::Exit(_) => {
+                    save(&viewport);
                     *control_flow = ControlFlow::Exit;
                 }
             };
         }
         match event {
             Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(size) => {
-                    if let Err(err) = pixels.resize_surface(size.width, size.height) {
+                WindowEvent::CloseRequested => {
+                    // The emulator replies with an exit once any recording is finished
+                    let sent = cmd_tx.send(EmulatorCommand::Quit);
+                    if sent.is_err() {
+                        save(&viewport);
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                WindowEvent::Resized(new_size) if new_size.width > 0 && new_size.height > 0 => {
+                    size = new_size;
+                    if let Err(err) = pixels
+                        .resize_surface(size.width, size.height)
+                        .and_then(|()| pixels.resize_buffer(size.width, size.height))
+                    {
                         error!("Failed to resize window: {}", err);
                         *control_flow = ControlFlow::Exit;
                     }
+                    if window.fullscreen().is_none() {
+                        let logical = size.to_logical::<u32>(window.scale_factor());
+                        let scale = viewport.fit_scale((logical.width, logical.height), FRAME);
+                        viewport.scale = scale.max(1);
+                    }
+                    redraw = true;
+                }
+                WindowEvent::ModifiersChanged(state) => modifiers = state,
+
+                // Releases go to the focused window, so let go of everything on losing focus
+                WindowEvent::Focused(false) => {
+                    if !held_keys.is_empty() {
+                        held_keys.clear();
+                        cmd_tx.send(EmulatorCommand::KeyUp).ok();
+                    }
+                    if apple_keys != (false, false) {
+                        apple_keys = (false, false);
+                        cmd_tx.send(EmulatorCommand::AppleKeys(false, false)).ok();
+                    }
+                }
+                WindowEvent::KeyboardInput { event, .. } => {
+                    let pressed = event.state == ElementState::Pressed;
+                    let apple = match event.physical_key {
+                        KeyCode::AltLeft => (pressed, apple_keys.1),
+                        KeyCode::AltRight => (apple_keys.0, pressed),
+                        _ => apple_keys,
+                    };
+                    if apple != apple_keys {
+                        apple_keys = apple;
+                        cmd_tx
+                            .send(EmulatorCommand::AppleKeys(apple.0, apple.1))
+                            .ok();
+                    }
+
+                    // The //e sees a key as held until every key is released
+                    let code = host_key(&event.logical_key)
+                        .and_then(|key| apple_key(key, modifiers.control_key()));
+                    if let (true, Some(code)) = (pressed, code) {
+                        held_keys.insert(event.physical_key);
+                        cmd_tx.send(EmulatorCommand::KeyDown(code)).ok();
+                    } else if !pressed
+                        && held_keys.remove(&event.physical_key)
+                        && held_keys.is_empty()
+                    {
+                        cmd_tx.send(EmulatorCommand::KeyUp).ok();
+                    }
+
+                    // F9 toggles 4:3, F10 integer or fit scaling and F11 fullscreen
+                    if pressed && !event.repeat {
+                        match event.physical_key {
+                            KeyCode::F9 => viewport.aspect = !viewport.aspect,
+                            KeyCode::F10 => {
+                                viewport.scaling = match viewport.scaling {
+                                    Scaling::Integer => Scaling::Fit,
+                                    Scaling::Fit => Scaling::Integer,
+                                }
+                            }
+                            KeyCode::F11 => match window.fullscreen() {
+                                Some(_) => window.set_fullscreen(None),
+                                None => window.set_fullscreen(Some(Fullscreen::Borderless(None))),
+                            },
+                            _ => {}
+                        }
+                        redraw = true;
+                    }
                 }
                 _ => {}
             },
-            Event::MainEventsCleared => {
-                // window.request_redraw();
-            }
             Event::RedrawRequested(_) => {
                 if let Err(err) = pixels.render() {
                     error!("Failed to draw: {}", err);
@@ -198,19 +750,53 @@ fn start_display_thread(gui_rx: mpsc::Receiver<DisplayCommand>) {
             }
             _ => {}
         }
+        if redraw {
+            viewport.blit(&frame, FRAME, pixels.frame_mut(), (size.width, size.height));
+            window.request_redraw();
+        }
     })
 }
 
+fn exit_with_error(err: String) -> ! {
+    error!("{}", err);
+    eprintln!("{}", err);
+    std::process::exit(1);
+}
+
+fn print_rom_info(file: &Path) {
+    match std::fs::read(file) {
+        Ok(data) => println!("File:  {}\n{}", file.display(), RomInfo::new(&data)),
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", file.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() {
     let config = Config::load();
+    let machine = match config.command.clone() {
+        Command::RomInfo { file } => return print_rom_info(&file),
+        Command::Machine(machine) => machine,
+    };
 
     info!("Starting RetroEmu");
 
     let (cmd_tx, cmd_rx) = mpsc::channel::<EmulatorCommand>();
     let (gui_tx, gui_rx) = mpsc::channel::<DisplayCommand>();
-    let gui_tx_2 = gui_tx.clone();
+    let (term_tx, term_rx) = mpsc::channel::<TerminalCommand>();
+    let display = config.display;
+    let viewport = config.viewport;
+    let viewport_path = config.viewport_path();
+    let running = config.screenshot_at_cycle.is_some();
 
-    start_emulation_thread(config, cmd_rx, gui_tx);
-    start_terminal_thread(cmd_tx, gui_tx_2);
-    start_display_thread(gui_rx);
+    if let Err(err) = start_emulation_thread(config, machine, cmd_rx, gui_tx, term_tx) {
+        exit_with_error(err);
+    }
+    start_terminal_thread(cmd_tx.clone(), term_rx, running);
+    match display {
+        DisplayKind::Window => start_display_thread(gui_rx, cmd_tx, viewport, viewport_path),
+        DisplayKind::Headless => run_headless(gui_rx),
+        DisplayKind::Tty => run_tty(gui_rx),
+    }
 }